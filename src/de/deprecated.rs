@@ -0,0 +1,122 @@
+//! Renamed keys that are still accepted
+
+use super::{Entry, Error, Warning};
+
+/// A key that was renamed, see [`super::Deserializer::add_deprecated_key`]
+pub(crate) struct DeprecatedKey {
+    pub old: String,
+    pub new: String,
+    pub message: String,
+}
+
+/// Rename the deprecated keys in `entries`, returning a warning for every use
+///
+/// Fails if the old and the new key of a rename are both set.
+pub(crate) fn rename(
+    entries: &mut [Entry],
+    deprecated: &[DeprecatedKey],
+) -> Result<Vec<Warning>, Error> {
+    let mut warnings = Vec::new();
+    for key in deprecated {
+        let Some(new) = entries.iter().find(|entry| entry.key == key.new) else {
+            continue;
+        };
+        if let Some(old) = entries.iter().find(|entry| entry.key == key.old) {
+            return Err(Error::DeprecatedKeyConflict {
+                old: key.old.clone(),
+                old_line: old.line,
+                new: key.new.clone(),
+                new_line: new.line,
+            });
+        }
+    }
+    for entry in entries.iter_mut() {
+        let Some(key) = deprecated.iter().find(|key| key.old == entry.key) else {
+            continue;
+        };
+        warnings.push(Warning::DeprecatedKey {
+            key: key.old.clone(),
+            replacement: key.new.clone(),
+            message: key.message.clone(),
+            line: entry.line,
+        });
+        entry.key.clone_from(&key.new);
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    use serde::Deserialize;
+
+    use crate::de::{Deserializer, Error, Warning};
+
+    #[derive(Debug, Deserialize)]
+    struct Pool {
+        #[serde(rename = "db.pool.max")]
+        max: usize,
+        #[serde(rename = "db.pool.min")]
+        min: usize,
+    }
+
+    fn deserializer(text: &str) -> Deserializer<Cursor<&str>> {
+        let mut de = Deserializer::from_str(text);
+        de.add_deprecated_key("db.maxconn", "db.pool.max", "renamed in 2.0");
+        de.add_deprecated_key("db.minconn", "db.pool.min", "renamed in 2.0");
+        de
+    }
+
+    #[test]
+    fn test_deprecated_keys() {
+        let mut de = deserializer("db.maxconn=20\ndb.pool.min=2");
+        let pool = Pool::deserialize(&mut de).unwrap();
+        assert_eq!((pool.max, pool.min), (20, 2));
+        assert_eq!(
+            de.warnings(),
+            [Warning::DeprecatedKey {
+                key: "db.maxconn".to_string(),
+                replacement: "db.pool.max".to_string(),
+                message: "renamed in 2.0".to_string(),
+                line: 1,
+            }]
+        );
+        assert_eq!(
+            de.warnings()[0].to_string(),
+            "line 1: `db.maxconn` is deprecated, use `db.pool.max` instead: renamed in 2.0"
+        );
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut de = deserializer("db.pool.max=20\n\ndb.minconn=2");
+        let handler_seen = seen.clone();
+        de.set_warning_handler(move |warning: &Warning| {
+            handler_seen.lock().unwrap().push(warning.to_string());
+        });
+        let pool = Pool::deserialize(de).unwrap();
+        assert_eq!((pool.max, pool.min), (20, 2));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            ["line 3: `db.minconn` is deprecated, use `db.pool.min` instead: renamed in 2.0"]
+        );
+    }
+
+    #[test]
+    fn test_conflict() {
+        let de = deserializer("db.pool.max=20\ndb.pool.min=2\ndb.maxconn=10");
+        let err = Pool::deserialize(de).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::DeprecatedKeyConflict {
+                    old_line: 3,
+                    new_line: 1,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        assert_eq!(err.line(), Some(3));
+    }
+}
@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 use super::Error;
 use serde::{
     de::{self, IntoDeserializer},
@@ -6,13 +8,68 @@ use serde::{
 
 pub(crate) struct FieldDeserializer(pub String);
 
+/// Parse `value`, describing the accepted values if it fails
+fn parse<T>(value: String, expected: &'static str) -> Result<T, Error>
+where
+    T: FromStr,
+    Error: From<T::Err>,
+{
+    value.parse().map_err(|e| Error::InvalidValue {
+        value,
+        expected,
+        key: None,
+        line: None,
+        source: Box::new(Error::from(e)),
+    })
+}
+
+/// Call `$make!` for each method that parses the value, with a description of the
+/// accepted values
+macro_rules! parsed_fns {
+    ($make:ident) => {
+        $make!(deserialize_bool, visit_bool, "`true` or `false`");
+        $make!(deserialize_u8, visit_u8, "an integer between 0 and 255");
+        $make!(deserialize_u16, visit_u16, "an integer between 0 and 65535");
+        $make!(
+            deserialize_u32,
+            visit_u32,
+            "an integer between 0 and 4294967295"
+        );
+        $make!(
+            deserialize_u64,
+            visit_u64,
+            "an integer between 0 and 18446744073709551615"
+        );
+        $make!(deserialize_u128, visit_u128, "a non-negative integer");
+        $make!(deserialize_i8, visit_i8, "an integer between -128 and 127");
+        $make!(
+            deserialize_i16,
+            visit_i16,
+            "an integer between -32768 and 32767"
+        );
+        $make!(
+            deserialize_i32,
+            visit_i32,
+            "an integer between -2147483648 and 2147483647"
+        );
+        $make!(
+            deserialize_i64,
+            visit_i64,
+            "an integer between -9223372036854775808 and 9223372036854775807"
+        );
+        $make!(deserialize_i128, visit_i128, "an integer");
+        $make!(deserialize_f32, visit_f32, "a floating point number");
+        $make!(deserialize_f64, visit_f64, "a floating point number");
+    };
+}
+
 macro_rules! make_fn {
-    ($deserialize_fn:ident, $visit_fn:ident) => {
+    ($deserialize_fn:ident, $visit_fn:ident, $expected:literal) => {
         fn $deserialize_fn<V>(self, visitor: V) -> Result<V::Value, Self::Error>
         where
             V: de::Visitor<'de>,
         {
-            visitor.$visit_fn(self.0.parse()?)
+            visitor.$visit_fn(parse(self.0, $expected)?)
         }
     };
 }
@@ -112,21 +169,7 @@ impl<'de> de::Deserializer<'de> for FieldDeserializer {
         visitor.visit_string(self.0)
     }
 
-    make_fn!(deserialize_bool, visit_bool);
-    make_fn!(deserialize_u8, visit_u8);
-    make_fn!(deserialize_u16, visit_u16);
-    make_fn!(deserialize_u32, visit_u32);
-    make_fn!(deserialize_u64, visit_u64);
-    make_fn!(deserialize_u128, visit_u128);
-
-    make_fn!(deserialize_i8, visit_i8);
-    make_fn!(deserialize_i16, visit_i16);
-    make_fn!(deserialize_i32, visit_i32);
-    make_fn!(deserialize_i64, visit_i64);
-    make_fn!(deserialize_i128, visit_i128);
-
-    make_fn!(deserialize_f32, visit_f32);
-    make_fn!(deserialize_f64, visit_f64);
+    parsed_fns!(make_fn);
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
@@ -141,12 +184,16 @@ impl<'de> de::Deserializer<'de> for FieldDeserializer {
 
     fn deserialize_newtype_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
+        if name == crate::value::TOKEN {
+            // A `Value` keeps the raw text
+            return visitor.visit_string(self.0);
+        }
         visitor.visit_newtype_struct(self)
     }
 
@@ -169,6 +216,312 @@ impl<'de> de::Deserializer<'de> for FieldDeserializer {
     }
 }
 
+/// A [`FieldDeserializer`] that notes whether its value was ignored
+///
+/// Visitors ask for [`de::Deserializer::deserialize_ignored_any`] for keys that
+/// none of their fields use.
+pub(crate) struct TrackingDeserializer<'a> {
+    pub value: String,
+    pub ignored: &'a mut bool,
+}
+
+macro_rules! forward_to_field {
+    ($($deserialize_fn:ident)*) => {
+        $(
+            fn $deserialize_fn<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: de::Visitor<'de>,
+            {
+                FieldDeserializer(self.value).$deserialize_fn(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for TrackingDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        *self.ignored = true;
+        FieldDeserializer(self.value).deserialize_ignored_any(visitor)
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_field! {
+        deserialize_any deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32
+        deserialize_u64 deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char
+        deserialize_str deserialize_string deserialize_bytes deserialize_byte_buf
+        deserialize_option deserialize_unit deserialize_seq deserialize_map
+        deserialize_identifier
+    }
+}
+
+/// A [`FieldDeserializer`] that stands in a placeholder for a value that fails to parse
+///
+/// This lets validation note the error and carry on, since the visitor would not
+/// be resumable once it saw the error.
+pub(crate) struct RecoveringDeserializer<'a> {
+    pub value: String,
+    pub rejected: &'a mut Option<Error>,
+}
+
+macro_rules! recover_fn {
+    ($deserialize_fn:ident, $visit_fn:ident, $expected:literal) => {
+        fn $deserialize_fn<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: de::Visitor<'de>,
+        {
+            match parse(self.value, $expected) {
+                Ok(v) => visitor.$visit_fn(v),
+                Err(e) => {
+                    *self.rejected = Some(e);
+                    PlaceholderDeserializer.$deserialize_fn(visitor)
+                }
+            }
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for RecoveringDeserializer<'_> {
+    type Error = Error;
+
+    parsed_fns!(recover_fn);
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if self.value.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if name == crate::value::TOKEN {
+            return visitor.visit_string(self.value);
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        FieldDeserializer(self.value).deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_field! {
+        deserialize_any deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_unit deserialize_seq
+        deserialize_map deserialize_identifier deserialize_ignored_any
+    }
+}
+
+/// Stands in for a value that was rejected or is missing while validating
+///
+/// The produced values are discarded, so they only need to be accepted by
+/// as many visitors as possible.
+pub(crate) struct PlaceholderDeserializer;
+
+macro_rules! placeholder_fn {
+    ($deserialize_fn:ident, $visit_fn:ident, $value:expr) => {
+        fn $deserialize_fn<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: de::Visitor<'de>,
+        {
+            visitor.$visit_fn($value)
+        }
+    };
+}
+
+impl<'de> de::Deserializer<'de> for PlaceholderDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    placeholder_fn!(deserialize_bool, visit_bool, false);
+    placeholder_fn!(deserialize_u8, visit_u8, 0);
+    placeholder_fn!(deserialize_u16, visit_u16, 0);
+    placeholder_fn!(deserialize_u32, visit_u32, 0);
+    placeholder_fn!(deserialize_u64, visit_u64, 0);
+    placeholder_fn!(deserialize_u128, visit_u128, 0);
+    placeholder_fn!(deserialize_i8, visit_i8, 0);
+    placeholder_fn!(deserialize_i16, visit_i16, 0);
+    placeholder_fn!(deserialize_i32, visit_i32, 0);
+    placeholder_fn!(deserialize_i64, visit_i64, 0);
+    placeholder_fn!(deserialize_i128, visit_i128, 0);
+    placeholder_fn!(deserialize_f32, visit_f32, 0.0);
+    placeholder_fn!(deserialize_f64, visit_f64, 0.0);
+    placeholder_fn!(deserialize_char, visit_char, ' ');
+    placeholder_fn!(deserialize_str, visit_str, "");
+    placeholder_fn!(deserialize_string, visit_str, "");
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_none()
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let variant = variants.first().copied().unwrap_or_default();
+        visitor.visit_enum(FieldDeserializer(variant.to_string()))
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use serde::Deserializer;
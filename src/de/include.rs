@@ -0,0 +1,452 @@
+//! Loading files with `include` directives
+
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::{Component, Path, PathBuf};
+
+use encoding_rs::{Encoding, WINDOWS_1252};
+
+use super::{Deserializer, Entry, Error};
+
+/// Load properties files that include other files
+///
+/// Like in [Apache Commons Configuration], a file can pull in other files with
+/// a directive like `include = common.properties`. The entries of the included file
+/// take the place of the directive, so keys that follow it override the included ones.
+///
+/// - The value is a comma-separated list of paths, relative to the including file
+/// - The last path component can be a glob pattern with `*` and `?`, e.g.
+///   `conf.d/*.properties`. Matching files are included in lexicographic order.
+/// - Files included with the optional directive (`includeoptional` by default) may
+///   be missing, while a missing file for the regular directive is an error
+/// - Cycles of includes fail with [`Error::IncludeCycle`]
+/// - With [`IncludeLoader::set_root`], files outside of the root directory fail with
+///   [`Error::IncludeOutsideRoot`], even when reached through `..` or symbolic links
+///
+/// ```no_run
+/// # use serde::Deserialize;
+/// # #[derive(Deserialize)]
+/// # struct Config {}
+/// use serde_java_properties::de::IncludeLoader;
+///
+/// let mut loader = IncludeLoader::new();
+/// loader.set_root("/etc/app");
+/// let config = Config::deserialize(loader.load("/etc/app/app.properties")?)?;
+/// # Ok::<(), serde_java_properties::de::Error>(())
+/// ```
+///
+/// [Apache Commons Configuration]: https://commons.apache.org/proper/commons-configuration/userguide/howto_properties.html#Includes
+#[derive(Debug, Clone)]
+pub struct IncludeLoader {
+    directive: String,
+    optional_directive: String,
+    root: Option<PathBuf>,
+    encoding: &'static Encoding,
+}
+
+impl Default for IncludeLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IncludeLoader {
+    /// Create a loader with the default directives and encoding (*ISO-8859-1*)
+    pub fn new() -> Self {
+        Self {
+            directive: "include".to_string(),
+            optional_directive: "includeoptional".to_string(),
+            root: None,
+            encoding: WINDOWS_1252,
+        }
+    }
+
+    /// Set the key of the include directive (default: `include`)
+    pub fn set_directive(&mut self, key: impl Into<String>) {
+        self.directive = key.into();
+    }
+
+    /// Set the key of the directive for optional includes (default: `includeoptional`)
+    pub fn set_optional_directive(&mut self, key: impl Into<String>) {
+        self.optional_directive = key.into();
+    }
+
+    /// Only allow files within this directory
+    pub fn set_root(&mut self, root: impl Into<PathBuf>) {
+        self.root = Some(root.into());
+    }
+
+    /// Set the encoding of all files
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Load the file at `path` and all files it includes
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<Deserializer<io::Empty>, Error> {
+        Ok(Deserializer::from_entries(
+            self.load_entries(path.as_ref())?,
+        ))
+    }
+
+    pub(crate) fn load_entries(&self, path: &Path) -> Result<Vec<Entry>, Error> {
+        let root = match &self.root {
+            Some(root) => Some(fs::canonicalize(root).map_err(|e| Error::in_file(root, e))?),
+            None => None,
+        };
+        let mut entries = Vec::new();
+        let path = self
+            .check(path, root.as_deref())
+            .map_err(|e| Error::in_file(path, e))?;
+        self.load_file(&path, root.as_deref(), &mut Vec::new(), &mut entries)?;
+        Ok(entries)
+    }
+
+    fn load_file(
+        &self,
+        path: &Path,
+        root: Option<&Path>,
+        stack: &mut Vec<PathBuf>,
+        out: &mut Vec<Entry>,
+    ) -> Result<(), Error> {
+        if let Some(start) = stack.iter().position(|p| p == path) {
+            let mut chain = stack.split_off(start);
+            chain.push(path.to_owned());
+            return Err(Error::IncludeCycle { chain });
+        }
+        let file = self.open(path, root).map_err(|e| Error::in_file(path, e))?;
+        let mut de = Deserializer::from_reader_with_encoding(BufReader::new(file), self.encoding);
+        de.set_source_name(path.display().to_string());
+        let entries = de.into_entries().map_err(|e| Error::in_file(path, e))?;
+
+        stack.push(path.to_owned());
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for entry in entries {
+            let optional = entry.key == self.optional_directive;
+            if !optional && entry.key != self.directive {
+                out.push(entry);
+                continue;
+            }
+            for pattern in entry.value.split(',').map(str::trim) {
+                if pattern.is_empty() {
+                    continue;
+                }
+                let paths = self
+                    .expand(&dir.join(pattern), root, optional)
+                    .map_err(|e| Error::in_file(path, e))?;
+                for included in paths {
+                    self.load_file(&included, root, stack, out)?;
+                }
+            }
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    /// Find the files that an include refers to
+    fn expand(
+        &self,
+        path: &Path,
+        root: Option<&Path>,
+        optional: bool,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let pattern = path.file_name().and_then(|name| name.to_str());
+        let candidates = match pattern.filter(|p| p.contains(['*', '?'])) {
+            Some(pattern) => {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                let mut matches = Vec::new();
+                // The directory is checked before it is listed, so that nothing outside
+                // of the root is read
+                let dir = match self.check(dir, root) {
+                    Ok(dir) => dir,
+                    Err(Error::Io(e)) if optional && e.kind() == io::ErrorKind::NotFound => {
+                        return Ok(matches)
+                    }
+                    Err(Error::Io(e)) => return Err(Error::in_file(dir, e)),
+                    Err(e) => return Err(e),
+                };
+                let entries = fs::read_dir(&dir).map_err(|e| Error::in_file(&dir, e))?;
+                for entry in entries {
+                    let entry = entry.map_err(|e| Error::in_file(&dir, e))?;
+                    let name = entry.file_name();
+                    if name.to_str().is_some_and(|name| glob_match(pattern, name)) {
+                        matches.push(entry.path());
+                    }
+                }
+                if matches.is_empty() && !optional {
+                    let e = io::Error::new(io::ErrorKind::NotFound, "no file matches the pattern");
+                    return Err(Error::in_file(path, e));
+                }
+                matches.sort();
+                matches
+            }
+            None => vec![path.to_owned()],
+        };
+        let mut paths = Vec::new();
+        for candidate in candidates {
+            match self.check(&candidate, root) {
+                Ok(path) => paths.push(path),
+                Err(Error::Io(e)) if optional && e.kind() == io::ErrorKind::NotFound => {}
+                Err(Error::Io(e)) => return Err(Error::in_file(&candidate, e)),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Resolve `path` and make sure it is within the root
+    fn check(&self, path: &Path, root: Option<&Path>) -> Result<PathBuf, Error> {
+        let Some(root) = root else {
+            return Ok(fs::canonicalize(path)?);
+        };
+        let outside = |path| Error::IncludeOutsideRoot {
+            path,
+            root: root.to_owned(),
+        };
+        // Paths that lead out of the root with `..` are rejected before they are looked
+        // up, so that the error doesn't tell whether they exist
+        if path.is_absolute() && !normalize(path).starts_with(root) {
+            return Err(outside(normalize(path)));
+        }
+        let path = fs::canonicalize(path)?;
+        if !path.starts_with(root) {
+            return Err(outside(path));
+        }
+        Ok(path)
+    }
+
+    /// Open a file whose path [`IncludeLoader::check`] returned
+    ///
+    /// The path is checked again once the file is open, in case it was replaced in the
+    /// meantime, e.g. with a symbolic link that leads out of the root.
+    fn open(&self, path: &Path, root: Option<&Path>) -> Result<fs::File, Error> {
+        let file = fs::File::open(path)?;
+        if let Some(root) = root {
+            let checked = self.check(path, Some(root))?;
+            if !same_file(&file, &checked)? {
+                return Err(Error::IncludeOutsideRoot {
+                    path: checked,
+                    root: root.to_owned(),
+                });
+            }
+        }
+        Ok(file)
+    }
+}
+
+/// Resolve `.` and `..` in `path` without looking at the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Whether `file` is the file at `path`
+#[cfg(unix)]
+fn same_file(file: &fs::File, path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (opened, found) = (file.metadata()?, fs::metadata(path)?);
+    Ok(opened.dev() == found.dev() && opened.ino() == found.ino())
+}
+
+/// Whether `file` is the file at `path`
+///
+/// Without a portable file identity, this only relies on checking the path again.
+#[cfg(not(unix))]
+fn same_file(_file: &fs::File, _path: &Path) -> io::Result<bool> {
+    Ok(true)
+}
+
+/// Match a file name against a pattern with `*` and `?` wildcards
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // The position of the last `*` in the pattern and the name position it was tried at
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::Path;
+
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    use super::{glob_match, IncludeLoader};
+    use crate::de::Error;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.properties", "a.properties"));
+        assert!(glob_match("*.properties", ".properties"));
+        assert!(glob_match("a?c*", "abc"));
+        assert!(glob_match("*a*b", "xxaxxb"));
+        assert!(!glob_match("*.properties", "a.properties.bak"));
+        assert!(!glob_match("a?c", "ac"));
+    }
+
+    /// Create a temporary directory with the given files
+    fn tree(files: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn load(loader: &IncludeLoader, path: &Path) -> Result<BTreeMap<String, String>, Error> {
+        BTreeMap::deserialize(loader.load(path)?)
+    }
+
+    #[test]
+    fn test_include() {
+        let tmp = tree(&[
+                ("app/app.properties", "a=app\ninclude = common.properties, conf.d/*.properties\nb=app\nincludeoptional = missing.properties, none.d/*"),
+                ("app/common.properties", "a=common\nb=common\nc=common"),
+                ("app/conf.d/2.properties", "d=2"),
+                ("app/conf.d/1.properties", "d=1\ne=1"),
+                ("app/conf.d/ignored.txt", "d=txt"),
+            ]);
+        let dir = tmp.path();
+        let mut loader = IncludeLoader::new();
+        loader.set_root(dir);
+        let map = load(&loader, &dir.join("app/app.properties")).unwrap();
+        let expected = [
+            ("a", "common"),
+            ("b", "app"),
+            ("c", "common"),
+            ("d", "2"),
+            ("e", "1"),
+        ];
+        assert_eq!(
+            map,
+            expected.map(|(k, v)| (k.to_string(), v.to_string())).into()
+        );
+
+        let mut loader = IncludeLoader::new();
+        loader.set_directive("@include");
+        let map = load(&loader, &dir.join("app/app.properties")).unwrap();
+        assert_eq!(map["include"], "common.properties, conf.d/*.properties");
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let tmp = tree(&[
+            ("root/a.properties", "include=b.properties"),
+            ("root/b.properties", "x=1\ninclude=sub/../a.properties"),
+            ("root/escape.properties", "include=../secret.properties"),
+            ("root/missing.properties", "include=missing.properties"),
+            ("secret.properties", "password=hunter2"),
+        ]);
+        let root = tmp.path().join("root");
+        let mut loader = IncludeLoader::new();
+        loader.set_root(&root);
+
+        let err = load(&loader, &root.join("escape.properties")).unwrap_err();
+        let Error::File { source, .. } = err else {
+            panic!("unexpected error {}", err);
+        };
+        assert!(matches!(*source, Error::IncludeOutsideRoot { .. }));
+
+        let err = load(&loader, &root.join("missing.properties")).unwrap_err();
+        assert!(err.to_string().contains("missing.properties"));
+
+        fs::create_dir_all(root.join("sub")).unwrap();
+        let err = load(&loader, &root.join("a.properties")).unwrap_err();
+        let Error::IncludeCycle { chain } = err else {
+            panic!("unexpected error {}", err);
+        };
+        let names: Vec<_> = chain.iter().map(|p| p.file_name().unwrap()).collect();
+        assert_eq!(names, ["a.properties", "b.properties", "a.properties"]);
+    }
+
+    #[test]
+    fn test_include_glob_outside_root() {
+        let tmp = tree(&[
+            ("root/exists.properties", "include=../secret.d/*"),
+            ("root/missing.properties", "includeoptional=../missing.d/*"),
+            ("secret.d/password.properties", "password=hunter2"),
+        ]);
+        let root = tmp.path().join("root");
+        let mut loader = IncludeLoader::new();
+        loader.set_root(&root);
+
+        // Whether the directory exists or not, it isn't looked at
+        for name in ["exists.properties", "missing.properties"] {
+            let err = load(&loader, &root.join(name)).unwrap_err();
+            let Error::File { source, .. } = err else {
+                panic!("unexpected error {}", err);
+            };
+            let Error::IncludeOutsideRoot { path, .. } = *source else {
+                panic!("unexpected error {}", source);
+            };
+            assert!(!path.starts_with(&root));
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(tmp.path().join("secret.d"), root.join("link")).unwrap();
+            fs::write(root.join("link.properties"), "include=link/*").unwrap();
+            let err = load(&loader, &root.join("link.properties")).unwrap_err();
+            let Error::File { source, .. } = err else {
+                panic!("unexpected error {}", err);
+            };
+            assert!(matches!(*source, Error::IncludeOutsideRoot { .. }));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_include_replaced_after_check() {
+        let tmp = tree(&[
+            ("root/a.properties", "a=1"),
+            ("secret.properties", "password=hunter2"),
+        ]);
+        let root = fs::canonicalize(tmp.path().join("root")).unwrap();
+        let mut loader = IncludeLoader::new();
+        loader.set_root(&root);
+
+        let path = loader
+            .check(&root.join("a.properties"), Some(&root))
+            .unwrap();
+        assert!(loader.open(&path, Some(&root)).is_ok());
+        fs::remove_file(&path).unwrap();
+        std::os::unix::fs::symlink(tmp.path().join("secret.properties"), &path).unwrap();
+        let err = loader.open(&path, Some(&root)).unwrap_err();
+        assert!(matches!(err, Error::IncludeOutsideRoot { .. }), "{err}");
+    }
+}
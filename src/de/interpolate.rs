@@ -0,0 +1,462 @@
+//! Resolution of `${key}` placeholders
+
+use std::collections::HashMap;
+use std::{env, fs, io, path::Path};
+
+use super::{Entry, Error};
+
+/// The error type of [`Resolver`]s
+pub type ResolverError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A source of values for prefixed placeholders, such as `${env:HOME}`
+///
+/// See [`super::Deserializer::add_resolver`]
+pub trait Resolver {
+    /// Get the value for `name`, or `None` if there is none
+    ///
+    /// For the placeholder `${env:HOME}`, `name` is `HOME`.
+    fn resolve(&self, name: &str) -> Result<Option<String>, ResolverError>;
+}
+
+impl<F> Resolver for F
+where
+    F: Fn(&str) -> Result<Option<String>, ResolverError>,
+{
+    fn resolve(&self, name: &str) -> Result<Option<String>, ResolverError> {
+        self(name)
+    }
+}
+
+/// Resolves placeholders from environment variables
+///
+/// Variables that are not set, or not valid unicode, resolve to `None`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EnvResolver;
+
+impl Resolver for EnvResolver {
+    fn resolve(&self, name: &str) -> Result<Option<String>, ResolverError> {
+        Ok(env::var(name).ok())
+    }
+}
+
+/// Resolves placeholders from the contents of files
+///
+/// The name is the path of the file. A single trailing line break is removed,
+/// since it is usually not part of the value, e.g. for secrets mounted as files.
+/// Files that don't exist resolve to `None`, other I/O errors fail.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileResolver;
+
+impl Resolver for FileResolver {
+    fn resolve(&self, name: &str) -> Result<Option<String>, ResolverError> {
+        match fs::read_to_string(Path::new(name)) {
+            Ok(mut text) => {
+                if text.ends_with('\n') {
+                    text.pop();
+                    if text.ends_with('\r') {
+                        text.pop();
+                    }
+                }
+                Ok(Some(text))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The resolvers registered on a deserializer, by prefix
+pub(crate) type Resolvers = Vec<(String, Box<dyn Resolver + Send + Sync>)>;
+
+/// The default of [`super::Deserializer::set_max_placeholder_depth`]
+pub(crate) const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Replace the placeholders in all values
+///
+/// Placeholders are nested at most `max_depth` levels deep, counting both references
+/// and defaults, and no value may grow longer than `max_value_length` bytes.
+///
+/// See [`super::Deserializer::set_interpolate`]
+pub(crate) fn interpolate(
+    entries: &mut [Entry],
+    resolvers: &Resolvers,
+    max_depth: usize,
+    max_value_length: Option<usize>,
+) -> Result<(), Error> {
+    let mut interpolator = Interpolator {
+        entries,
+        resolvers,
+        max_depth,
+        max_value_length,
+        depth: 0,
+        index: entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.key.as_str(), i))
+            .collect(),
+        states: entries.iter().map(|_| State::Unresolved).collect(),
+        stack: Vec::new(),
+    };
+    let values = (0..entries.len())
+        .map(|i| interpolator.resolve(i))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (entry, value) in entries.iter_mut().zip(values) {
+        entry.value = value;
+    }
+    Ok(())
+}
+
+enum State {
+    Unresolved,
+    InProgress,
+    Resolved(String),
+}
+
+struct Interpolator<'a> {
+    entries: &'a [Entry],
+    resolvers: &'a Resolvers,
+    max_depth: usize,
+    max_value_length: Option<usize>,
+    /// The number of texts that are being expanded
+    depth: usize,
+    /// The index of the last definition of each key
+    index: HashMap<&'a str, usize>,
+    states: Vec<State>,
+    /// The entries that are being resolved, outermost first
+    stack: Vec<usize>,
+}
+
+impl Interpolator<'_> {
+    fn resolve(&mut self, i: usize) -> Result<String, Error> {
+        match &self.states[i] {
+            State::Resolved(value) => return Ok(value.clone()),
+            State::InProgress => {
+                let start = self.stack.iter().position(|&j| j == i).unwrap();
+                let chain = self.stack[start..]
+                    .iter()
+                    .chain(std::iter::once(&i))
+                    .map(|&j| (self.entries[j].key.clone(), self.entries[j].line))
+                    .collect();
+                return Err(Error::PlaceholderCycle { chain });
+            }
+            State::Unresolved => {}
+        }
+        self.states[i] = State::InProgress;
+        self.stack.push(i);
+        let value = self.expand(&self.entries[i].value, i)?;
+        self.stack.pop();
+        self.states[i] = State::Resolved(value.clone());
+        Ok(value)
+    }
+
+    /// Replace the placeholders in `text`, which is (part of) the value of entry `owner`
+    fn expand(&mut self, text: &str, owner: usize) -> Result<String, Error> {
+        if self.depth == self.max_depth {
+            return Err(Error::PlaceholderTooDeep {
+                key: self.entries[owner].key.clone(),
+                line: self.entries[owner].line,
+                limit: self.max_depth,
+            });
+        }
+        self.depth += 1;
+        let out = self.expand_placeholders(text, owner)?;
+        self.depth -= 1;
+        Ok(out)
+    }
+
+    fn expand_placeholders(&mut self, text: &str, owner: usize) -> Result<String, Error> {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(pos) = rest.find('$') {
+            out.push_str(&rest[..pos]);
+            rest = &rest[pos..];
+            if let Some(tail) = rest.strip_prefix("$${") {
+                out.push_str("${");
+                rest = tail;
+            } else if let Some(tail) = rest.strip_prefix("${") {
+                let Some(end) = find_close(tail) else {
+                    // Not terminated, so not a placeholder
+                    out.push_str(rest);
+                    return Ok(out);
+                };
+                let placeholder = &rest[..end + 3];
+                out.push_str(&self.substitute(&tail[..end], placeholder, owner)?);
+                self.check_length(&out, owner)?;
+                rest = &tail[end + 1..];
+            } else {
+                out.push('$');
+                rest = &rest[1..];
+            }
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Find the value for the contents of a placeholder
+    fn substitute(
+        &mut self,
+        inner: &str,
+        placeholder: &str,
+        owner: usize,
+    ) -> Result<String, Error> {
+        let prefixed = inner.split_once(':').and_then(|(prefix, rest)| {
+            let (_, resolver) = self.resolvers.iter().find(|(p, _)| p == prefix)?;
+            Some((resolver, rest))
+        });
+        let (resolver, rest) = match prefixed {
+            Some((resolver, rest)) => (Some(resolver), rest),
+            None => (None, inner),
+        };
+        let (name, default) = match rest.split_once(':') {
+            Some((name, default)) => (name, Some(default)),
+            None => (rest, None),
+        };
+        match resolver {
+            Some(resolver) => match resolver.resolve(name) {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                Err(source) => {
+                    return Err(Error::ResolverFailed {
+                        key: self.entries[owner].key.clone(),
+                        line: self.entries[owner].line,
+                        placeholder: placeholder.to_string(),
+                        source,
+                    })
+                }
+            },
+            None => {
+                if let Some(&i) = self.index.get(name) {
+                    return self.resolve(i);
+                }
+            }
+        }
+        match default {
+            Some(default) => self.expand(default, owner),
+            None => Err(Error::UnresolvedPlaceholder {
+                key: self.entries[owner].key.clone(),
+                line: self.entries[owner].line,
+                placeholder: placeholder.to_string(),
+            }),
+        }
+    }
+}
+
+impl Interpolator<'_> {
+    /// Fail as soon as a value grows longer than the limit
+    fn check_length(&self, value: &str, owner: usize) -> Result<(), Error> {
+        match self.max_value_length {
+            Some(limit) if value.len() > limit => Err(Error::ValueTooLong {
+                line: self.entries[owner].line,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Find the `}` that closes a placeholder, skipping over nested placeholders
+fn find_close(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '$' if matches!(chars.peek(), Some((_, '{'))) => {
+                chars.next();
+                depth += 1;
+            }
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    use super::{FileResolver, Resolver, ResolverError, Resolvers, DEFAULT_MAX_DEPTH};
+    use crate::de::{Deserializer, Entry, Error, Limits};
+
+    fn interpolate(entries: &mut [Entry], resolvers: &Resolvers) -> Result<(), Error> {
+        super::interpolate(entries, resolvers, DEFAULT_MAX_DEPTH, None)
+    }
+
+    fn entries(pairs: &[(&str, &str)]) -> Vec<Entry> {
+        pairs
+            .iter()
+            .enumerate()
+            .map(|(i, (key, value))| Entry {
+                line: i + 1,
+                key: key.to_string(),
+                value: value.to_string(),
+                source: None,
+                raw: None,
+            })
+            .collect()
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> Vec<String> {
+        let mut entries = entries(pairs);
+        interpolate(&mut entries, &Resolvers::new()).unwrap();
+        entries.into_iter().map(|e| e.value).collect()
+    }
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(
+            values(&[
+                ("log.dir", "${base.dir}/logs"),
+                ("base.dir", "/opt/${name}"),
+                ("name", "app"),
+            ]),
+            ["/opt/app/logs", "/opt/app", "app"]
+        );
+        assert_eq!(
+            values(&[
+                ("a", "${missing:fallback}"),
+                ("b", "${missing:${a}-${c:}}"),
+                ("c", "x"),
+            ]),
+            ["fallback", "fallback-x", "x"]
+        );
+        assert_eq!(
+            values(&[("a", "$${a} $5 ${unterminated"), ("b", "${a}")]),
+            ["${a} $5 ${unterminated", "${a} $5 ${unterminated"]
+        );
+        // The last definition wins
+        assert_eq!(
+            values(&[("a", "1"), ("b", "${a}"), ("a", "2")]),
+            ["1", "2", "2"]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut e = entries(&[("a", "x"), ("b", "${a}${c}")]);
+        match interpolate(&mut e, &Resolvers::new()).unwrap_err() {
+            Error::UnresolvedPlaceholder {
+                key,
+                line,
+                placeholder,
+            } => assert_eq!((key.as_str(), line, placeholder.as_str()), ("b", 2, "${c}")),
+            e => panic!("unexpected error {}", e),
+        }
+
+        let mut e = entries(&[("a", "${b}"), ("b", "${c}"), ("c", "${b}")]);
+        let err = interpolate(&mut e, &Resolvers::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "placeholder cycle: `b` (line 2) -> `c` (line 3) -> `b` (line 2)"
+        );
+
+        // Long chains and deeply nested defaults fail instead of overflowing the stack
+        let mut e: Vec<_> = (0..10_000)
+            .map(|i| Entry {
+                line: i + 1,
+                key: format!("k{}", i),
+                value: format!("${{k{}}}", i + 1),
+                source: None,
+                raw: None,
+            })
+            .collect();
+        let err = interpolate(&mut e, &Resolvers::new()).unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                Error::PlaceholderTooDeep {
+                    line: 65,
+                    limit: 64,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        let nested = "${x:".repeat(10_000) + &"}".repeat(10_000);
+        let mut e = entries(&[("a", &nested)]);
+        let err = interpolate(&mut e, &Resolvers::new()).unwrap_err();
+        assert!(matches!(err, Error::PlaceholderTooDeep { line: 1, .. }));
+        let mut e = entries(&[("a", "${b}"), ("b", "${c}"), ("c", "x")]);
+        super::interpolate(&mut e, &Resolvers::new(), 3, None).unwrap();
+        let mut e = entries(&[("a", "${b}"), ("b", "${c}"), ("c", "x")]);
+        let err = super::interpolate(&mut e, &Resolvers::new(), 2, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "placeholders in `c` on line 3 are nested deeper than 2 levels"
+        );
+    }
+
+    #[test]
+    fn test_value_length() {
+        // Every value doubles the previous one, which would grow to 64 MiB
+        let mut text = "l0=xxxxxxxxxxxxxxxx\n".to_string();
+        for i in 1..23 {
+            text += &format!("l{}=${{l{}}}${{l{}}}\n", i, i - 1, i - 1);
+        }
+        let mut de = Deserializer::from_str(&text);
+        de.set_interpolate(true);
+        de.set_limits(Limits {
+            max_value_length: Some(1024),
+            ..Limits::default()
+        });
+        let err = BTreeMap::<String, String>::deserialize(de).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::ValueTooLong {
+                    line: 8,
+                    limit: 1024
+                }
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_resolvers() {
+        fn secrets(name: &str) -> Result<Option<String>, ResolverError> {
+            match name {
+                "db" => Ok(Some("hunter2".to_string())),
+                "broken" => Err("secret store unavailable".into()),
+                _ => Ok(None),
+            }
+        }
+        let resolvers: Resolvers = vec![("secret".to_string(), Box::new(secrets))];
+        assert_eq!(secrets.resolve("db").unwrap().as_deref(), Some("hunter2"));
+
+        let mut e = entries(&[
+            ("a", "${secret:db}"),
+            ("b", "${secret:missing:${a}}"),
+            ("c", "${other:db:x}"),
+        ]);
+        interpolate(&mut e, &resolvers).unwrap();
+        let values: Vec<_> = e.iter().map(|e| e.value.as_str()).collect();
+        assert_eq!(values, ["hunter2", "hunter2", "db:x"]);
+
+        let mut e = entries(&[("a", "1"), ("pw", "${secret:broken}")]);
+        let err = interpolate(&mut e, &resolvers).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to resolve ${secret:broken} in `pw` on line 2: secret store unavailable"
+        );
+
+        let mut e = entries(&[("pw", "${secret:missing}")]);
+        let err = interpolate(&mut e, &resolvers).unwrap_err();
+        assert!(matches!(err, Error::UnresolvedPlaceholder { .. }));
+    }
+
+    #[test]
+    fn test_file_resolver() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("secret");
+        std::fs::write(&path, "s3cr3t\r\n").unwrap();
+        let value = FileResolver.resolve(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(value.as_deref(), Some("s3cr3t"));
+        assert_eq!(FileResolver.resolve(path.to_str().unwrap()).unwrap(), None);
+    }
+}
@@ -0,0 +1,255 @@
+//! Merging several sources
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+use super::{Deserializer, Entry, Error};
+
+/// The ordinal of sources that don't set `config_ordinal`, as in MicroProfile Config
+pub const DEFAULT_ORDINAL: i64 = 100;
+
+/// Configuration merged from several sources
+///
+/// Sources are added from lowest to highest priority, and a key in a later source
+/// overrides the same key in all earlier ones. A source can change its priority with
+/// a `config_ordinal` key, like in [MicroProfile Config]: sources with a higher
+/// ordinal win, and sources with the same ordinal are applied in the order they
+/// were added. The default ordinal is [`DEFAULT_ORDINAL`]. The `config_ordinal`
+/// key itself is not passed on to the deserializer.
+///
+/// ```
+/// # use serde::Deserialize;
+/// use serde_java_properties::de::Layered;
+///
+/// #[derive(Deserialize)]
+/// struct Pool {
+///     min: usize,
+///     max: usize,
+/// }
+///
+/// let mut layers = Layered::new();
+/// layers.add_str("config_ordinal=500\nmax=3")?;
+/// layers.add_str("min=1\nmax=10")?;
+/// layers.add_str("min=2")?;
+///
+/// let pool = Pool::deserialize(layers.deserializer())?;
+/// assert_eq!((pool.min, pool.max), (2, 3));
+/// # Ok::<(), serde_java_properties::de::Error>(())
+/// ```
+///
+/// [MicroProfile Config]: https://download.eclipse.org/microprofile/microprofile-config-3.1/microprofile-config-spec-3.1.html#_configsource_ordering
+#[derive(Debug, Clone)]
+pub struct Layered {
+    layers: Vec<Layer>,
+    encoding: &'static Encoding,
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    ordinal: i64,
+    entries: Vec<Entry>,
+}
+
+impl Default for Layered {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Layered {
+    /// Create an empty configuration
+    ///
+    /// Readers and files are read as *ISO-8859-1* by default.
+    pub fn new() -> Self {
+        Self {
+            layers: Vec::new(),
+            encoding: WINDOWS_1252,
+        }
+    }
+
+    /// Set the encoding for the readers and files added after this call
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Add a source from a string
+    pub fn add_str(&mut self, text: &str) -> Result<&mut Self, Error> {
+        let de = Deserializer::from_reader_with_encoding(text.as_bytes(), UTF_8);
+        self.add_entries(de.into_entries()?)
+    }
+
+    /// Add a source from a string, and name it for [`Origin::Source`](super::Origin::Source)
+    pub fn add_named_str(&mut self, name: &str, text: &str) -> Result<&mut Self, Error> {
+        let mut de = Deserializer::from_reader_with_encoding(text.as_bytes(), UTF_8);
+        de.set_source_name(name);
+        self.add_entries(de.into_entries()?)
+    }
+
+    /// Add a source from a [`io::Read`] implementation
+    pub fn add_reader<R: io::Read>(&mut self, reader: R) -> Result<&mut Self, Error> {
+        let de = Deserializer::from_reader_with_encoding(reader, self.encoding);
+        self.add_entries(de.into_entries()?)
+    }
+
+    /// Add a source from a file
+    pub fn add_path(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, Error> {
+        let path = path.as_ref();
+        let entries = self.read_path(path)?;
+        self.add_entries(entries)
+            .map_err(|e| Error::in_file(path, e))
+    }
+
+    /// Add a source from a file, if it exists
+    pub fn add_optional_path(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, Error> {
+        let path = path.as_ref();
+        match self.read_path(path) {
+            Err(e) if e.is_not_found() => Ok(self),
+            result => self
+                .add_entries(result?)
+                .map_err(|e| Error::in_file(path, e)),
+        }
+    }
+
+    fn read_path(&self, path: &Path) -> Result<Vec<Entry>, Error> {
+        let file = fs::File::open(path).map_err(|e| Error::in_file(path, e))?;
+        let mut de = Deserializer::from_reader_with_encoding(BufReader::new(file), self.encoding);
+        de.set_source_name(path.display().to_string());
+        de.into_entries().map_err(|e| Error::in_file(path, e))
+    }
+
+    /// Add every `*.properties` file in a directory, in lexicographic order of their names
+    ///
+    /// A directory that doesn't exist is treated as empty.
+    pub fn add_dir(&mut self, dir: impl AsRef<Path>) -> Result<&mut Self, Error> {
+        let dir = dir.as_ref();
+        let entries = match fs::read_dir(dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(self),
+            result => result.map_err(|e| Error::in_file(dir, e))?,
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| Error::in_file(dir, e))?.path();
+            if path.extension().is_some_and(|ext| ext == "properties") && path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        for path in paths {
+            self.add_path(path)?;
+        }
+        Ok(self)
+    }
+
+    fn add_entries(&mut self, mut entries: Vec<Entry>) -> Result<&mut Self, Error> {
+        let mut ordinal = DEFAULT_ORDINAL;
+        if let Some(i) = entries.iter().rposition(|e| e.key == "config_ordinal") {
+            let entry = entries.remove(i);
+            ordinal = entry
+                .value
+                .trim()
+                .parse()
+                .map_err(|e| Error::InvalidValue {
+                    value: entry.value.clone(),
+                    expected: "an integer",
+                    key: Some(entry.key.clone()),
+                    line: Some(entry.line),
+                    source: Box::new(Error::ParseIntError(e)),
+                })?;
+            entries.retain(|e| e.key != "config_ordinal");
+        }
+        self.layers.push(Layer { ordinal, entries });
+        Ok(self)
+    }
+
+    /// Create a deserializer for the merged configuration
+    pub fn deserializer(&self) -> Deserializer<io::Empty> {
+        let mut layers: Vec<&Layer> = self.layers.iter().collect();
+        layers.sort_by_key(|layer| layer.ordinal);
+        let entries = layers.into_iter().flat_map(|l| l.entries.iter().cloned());
+        Deserializer::from_entries(last_wins(entries))
+    }
+}
+
+/// Keep only the last entry for each key, in the order of those last entries
+pub(crate) fn last_wins(entries: impl IntoIterator<Item = Entry>) -> Vec<Entry> {
+    let entries: Vec<Entry> = entries.into_iter().collect();
+    let mut last = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        last.insert(entry.key.clone(), i);
+    }
+    entries
+        .into_iter()
+        .enumerate()
+        .filter(|(i, entry)| last[&entry.key] == *i)
+        .map(|(_, entry)| entry)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    use super::Layered;
+    use crate::de::Error;
+
+    fn merged(layers: &Layered) -> Vec<(String, String)> {
+        let map = BTreeMap::<String, String>::deserialize(layers.deserializer()).unwrap();
+        map.into_iter().collect()
+    }
+
+    #[test]
+    fn test_ordinals() {
+        let mut layers = Layered::new();
+        layers.add_str("a=1\nb=1\nconfig_ordinal=200").unwrap();
+        layers.add_str("a=2\nb=2\nc=2").unwrap();
+        layers.add_str("config_ordinal=200\nb=3").unwrap();
+        layers.add_str("config_ordinal=-1\nc=4\nd=4").unwrap();
+        let expected = [("a", "1"), ("b", "3"), ("c", "2"), ("d", "4")];
+        assert_eq!(
+            merged(&layers),
+            expected.map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+
+        let err = layers.add_str("a=1\nconfig_ordinal=high").unwrap_err();
+        assert_eq!(err.line(), Some(2));
+    }
+
+    #[test]
+    fn test_paths() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("defaults.properties"), "a=defaults\nb=defaults").unwrap();
+        fs::write(dir.join("conf.d/20-b.properties"), "b=20").unwrap();
+        fs::write(dir.join("conf.d/10-a.properties"), "a=10\nb=10").unwrap();
+        fs::write(dir.join("conf.d/30.txt"), "a=txt").unwrap();
+
+        let mut layers = Layered::new();
+        layers
+            .add_path(dir.join("defaults.properties"))
+            .unwrap()
+            .add_optional_path(dir.join("override.properties"))
+            .unwrap()
+            .add_dir(dir.join("conf.d"))
+            .unwrap();
+        let expected = [("a", "10"), ("b", "20")];
+        assert_eq!(
+            merged(&layers),
+            expected.map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+        assert!(layers.add_path(dir.join("override.properties")).is_err());
+
+        fs::write(dir.join("bad.properties"), "a=1\nconfig_ordinal=high").unwrap();
+        let err = layers.add_path(dir.join("bad.properties")).unwrap_err();
+        assert!(matches!(&err, Error::File { path, .. } if path.ends_with("bad.properties")));
+        assert_eq!(err.line(), Some(2));
+    }
+}
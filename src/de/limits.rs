@@ -0,0 +1,345 @@
+use std::sync::{Arc, Mutex};
+use std::{fmt, io};
+
+use encoding_rs::{Decoder, DecoderResult, Encoding};
+
+/// Limits on the size of the input
+///
+/// These protect against untrusted input that would otherwise be read into memory
+/// without bound. Every limit is disabled (`None`) by default. See
+/// [`super::Deserializer::set_limits`].
+///
+/// The line-based limits are checked on the raw bytes, before the input is decoded,
+/// and assume an encoding in which line breaks and backslashes are single ASCII bytes,
+/// such as *ISO-8859-1* or *UTF-8*.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum number of bytes in a single physical line, excluding the line break
+    pub max_line_length: Option<usize>,
+    /// The maximum number of bytes in a value, after joining continuation lines and unescaping
+    ///
+    /// This is checked once a value has been read, and again after its placeholders
+    /// are resolved. It doesn't bound the memory used while reading a value, which
+    /// takes [`Limits::max_line_length`] together with
+    /// [`Limits::max_continuation_lines`], or [`Limits::max_total_bytes`].
+    pub max_value_length: Option<usize>,
+    /// The maximum number of continuation lines that may follow a single line
+    pub max_continuation_lines: Option<usize>,
+    /// The maximum number of key-value pairs
+    pub max_keys: Option<usize>,
+    /// The maximum number of bytes read from the input
+    pub max_total_bytes: Option<usize>,
+}
+
+impl Limits {
+    fn checks_bytes(&self) -> bool {
+        self.max_line_length.is_some()
+            || self.max_continuation_lines.is_some()
+            || self.max_total_bytes.is_some()
+    }
+}
+
+/// A limit that was exceeded while reading the raw input
+///
+/// This is passed through the [`java_properties::PropertiesIter`] as the payload
+/// of an [`io::Error`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Exceeded {
+    LineLength { line: usize, limit: usize },
+    ContinuationLines { line: usize, limit: usize },
+    TotalBytes { limit: usize },
+}
+
+impl fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "limit exceeded: {:?}", self)
+    }
+}
+
+impl std::error::Error for Exceeded {}
+
+/// The physical lines with malformed input that a [`LimitedReader`] found, with the reasons
+///
+/// This is shared with the [`super::Deserializer`], which reports them as
+/// [`super::Warning::MalformedLine`].
+pub(crate) type Malformed = Arc<Mutex<Vec<(usize, String)>>>;
+
+/// A reader that enforces the byte-based [`Limits`]
+///
+/// In lenient mode, it also notes byte sequences that are invalid in the encoding
+/// and a line continuation at the end of the input, which the parser would silently
+/// replace or drop.
+pub(crate) struct LimitedReader<R> {
+    inner: R,
+    limits: Limits,
+    /// Checks the encoding, and collects malformed input in lenient mode
+    lenient: Option<(Decoder, Malformed)>,
+    /// Whether the end of the input was reached
+    ended: bool,
+    total: usize,
+    /// The current 1-based physical line
+    line: usize,
+    line_length: usize,
+    /// The number of continuation lines that followed the current logical line so far
+    continuations: usize,
+    /// Whether only whitespace was seen on the current line
+    leading: bool,
+    /// Whether the current line is a comment
+    comment: bool,
+    /// Whether the previous line ended with a line continuation
+    continued: bool,
+    backslashes: usize,
+    /// Whether the previous byte was a `\r`, which might be followed by `\n`
+    after_cr: bool,
+}
+
+impl<R> LimitedReader<R> {
+    pub fn new(inner: R, limits: Limits) -> Self {
+        Self {
+            inner,
+            limits,
+            lenient: None,
+            ended: false,
+            total: 0,
+            line: 1,
+            line_length: 0,
+            continuations: 0,
+            leading: true,
+            comment: false,
+            continued: false,
+            backslashes: 0,
+            after_cr: false,
+        }
+    }
+
+    /// Note malformed input in `malformed`, decoding the input as `encoding`
+    pub fn check_malformed(&mut self, encoding: &'static Encoding, malformed: Malformed) {
+        self.lenient = Some((encoding.new_decoder_without_bom_handling(), malformed));
+    }
+
+    fn encoding_name(&self) -> &'static str {
+        let encoding = self.lenient.as_ref().map(|(decoder, _)| decoder.encoding());
+        encoding.map_or("", |encoding| encoding.name())
+    }
+
+    fn note(&self, line: usize, message: String) {
+        if let Some((_, malformed)) = &self.lenient {
+            let mut malformed = malformed.lock().unwrap();
+            if malformed.last().map(|(last, _)| *last) != Some(line) {
+                malformed.push((line, message));
+            }
+        }
+    }
+
+    /// The offsets of the bytes in `buf` that end a malformed byte sequence
+    fn invalid_bytes(&mut self, buf: &[u8]) -> Vec<usize> {
+        let mut offsets = Vec::new();
+        let Some((decoder, _)) = &mut self.lenient else {
+            return offsets;
+        };
+        let mut output = [0; 1024];
+        let mut offset = 0;
+        loop {
+            let (result, read, _) =
+                decoder.decode_to_utf8_without_replacement(&buf[offset..], &mut output, false);
+            offset += read;
+            match result {
+                DecoderResult::InputEmpty => return offsets,
+                DecoderResult::OutputFull => {}
+                DecoderResult::Malformed(_, after) => {
+                    offsets.push((offset - after as usize).saturating_sub(1));
+                }
+            }
+        }
+    }
+
+    /// Note a line continuation that has no line to continue
+    fn end_input(&mut self) {
+        if self.ended {
+            return;
+        }
+        self.ended = true;
+        if !self.comment && self.backslashes % 2 == 1 {
+            let message = "line continuation at the end of the input, the line was skipped";
+            self.note(self.line, message.to_string());
+        } else if self.continued && self.line_length == 0 && self.line > 1 {
+            let message = "line continuation at the end of the input";
+            self.note(self.line - 1, message.to_string());
+        }
+    }
+
+    fn scan(&mut self, byte: u8) -> Result<(), Exceeded> {
+        let after_cr = std::mem::replace(&mut self.after_cr, byte == b'\r');
+        match byte {
+            b'\n' if after_cr => {}
+            b'\r' | b'\n' => self.end_line()?,
+            _ => {
+                self.line_length += 1;
+                if let Some(limit) = self.limits.max_line_length {
+                    if self.line_length > limit {
+                        let line = self.line;
+                        return Err(Exceeded::LineLength { line, limit });
+                    }
+                }
+                if self.leading && !matches!(byte, b' ' | b'\t' | b'\x0c') {
+                    self.leading = false;
+                    self.comment = !self.continued && matches!(byte, b'#' | b'!');
+                }
+                if byte == b'\\' {
+                    self.backslashes += 1;
+                } else {
+                    self.backslashes = 0;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> Result<(), Exceeded> {
+        self.continued = !self.comment && self.backslashes % 2 == 1;
+        self.line += 1;
+        if self.continued {
+            self.continuations += 1;
+            if let Some(limit) = self.limits.max_continuation_lines {
+                if self.continuations > limit {
+                    let line = self.line;
+                    return Err(Exceeded::ContinuationLines { line, limit });
+                }
+            }
+        } else {
+            self.continuations = 0;
+        }
+        self.line_length = 0;
+        self.leading = true;
+        self.comment = false;
+        self.backslashes = 0;
+        Ok(())
+    }
+}
+
+impl<R: io::Read> io::Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if !self.limits.checks_bytes() && self.lenient.is_none() {
+            return Ok(n);
+        }
+        self.total += n;
+        if let Some(limit) = self.limits.max_total_bytes {
+            if self.total > limit {
+                return Err(io::Error::other(Exceeded::TotalBytes { limit }));
+            }
+        }
+        if n == 0 && !buf.is_empty() {
+            self.end_input();
+            return Ok(0);
+        }
+        let mut invalid = self.invalid_bytes(&buf[..n]).into_iter().peekable();
+        for (offset, &byte) in buf[..n].iter().enumerate() {
+            if invalid.next_if_eq(&offset).is_some() {
+                let message = format!(
+                    "invalid {} byte sequence, replaced with U+FFFD",
+                    self.encoding_name()
+                );
+                self.note(self.line, message);
+            }
+            self.scan(byte).map_err(io::Error::other)?;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{self, Read};
+
+    use serde::Deserialize;
+
+    use super::Limits;
+    use crate::de::{Deserializer, Error};
+
+    fn load<R: Read>(reader: R, limits: Limits) -> Result<HashMap<String, String>, Error> {
+        let mut de = Deserializer::from_reader(reader);
+        de.set_limits(limits);
+        HashMap::deserialize(&mut de)
+    }
+
+    #[test]
+    fn test_endless_line() {
+        let limits = Limits {
+            max_line_length: Some(1024),
+            ..Limits::default()
+        };
+        let input = io::Cursor::new("a=b\n").chain(io::repeat(b'x'));
+        let err = load(input, limits).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::LineTooLong {
+                line: 2,
+                limit: 1024
+            }
+        ));
+    }
+
+    #[test]
+    fn test_endless_continuation() {
+        let limits = Limits {
+            max_continuation_lines: Some(100),
+            ..Limits::default()
+        };
+        // An even number of backslashes doesn't continue the line
+        let input = io::Cursor::new("a=\\\\\nkey=\\\n").chain(RepeatSlice(b"x\\\r\n"));
+        let err = load(input, limits).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::TooManyContinuationLines {
+                line: 103,
+                limit: 100
+            }
+        ));
+
+        // Comments don't continue, even if they end with a backslash
+        let comments = "# comment \\\n".repeat(1000);
+        load(comments.as_bytes(), limits).unwrap();
+    }
+
+    #[test]
+    fn test_total_bytes() {
+        let limits = Limits {
+            max_total_bytes: Some(1 << 16),
+            ..Limits::default()
+        };
+        let err = load(RepeatSlice(b"k=v\n"), limits).unwrap_err();
+        assert!(matches!(err, Error::InputTooLarge { limit: 65536 }));
+
+        let input = "k=v\n".repeat(1 << 14);
+        load(input.as_bytes(), limits).unwrap();
+    }
+
+    #[test]
+    fn test_keys_and_values() {
+        let limits = Limits {
+            max_keys: Some(1000),
+            max_value_length: Some(4),
+            ..Limits::default()
+        };
+        let err = load(io::Cursor::new("a=1234\nb=\\\n  5678\\\n9"), limits).unwrap_err();
+        assert!(matches!(err, Error::ValueTooLong { line: 2, limit: 4 }));
+
+        let many: String = (0..1001).map(|i| format!("k{}=v\n", i)).collect();
+        let err = load(many.as_bytes(), limits).unwrap_err();
+        assert!(matches!(err, Error::TooManyKeys { limit: 1000 }));
+    }
+
+    /// Repeats a byte slice forever
+    struct RepeatSlice(&'static [u8]);
+
+    impl Read for RepeatSlice {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = self.0[i % self.0.len()];
+            }
+            Ok(buf.len() - buf.len() % self.0.len())
+        }
+    }
+}
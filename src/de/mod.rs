@@ -1,16 +1,55 @@
 //! Deserialization
 
-use encoding_rs::{Encoding, UTF_8};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
 use java_properties::LineContent::{Comment, KVPair};
 use java_properties::PropertiesIter;
-use serde::de::{self, IntoDeserializer, MapAccess, Visitor};
+use serde::de::{self, DeserializeOwned, IntoDeserializer, MapAccess, Visitor};
 use serde::forward_to_deserialize_any;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::io;
 use std::num::{ParseFloatError, ParseIntError};
+use std::path::{Path, PathBuf};
 use std::str::ParseBoolError;
+use std::sync::Arc;
 
+mod deprecated;
 mod field;
+mod include;
+mod interpolate;
+mod layered;
+mod limits;
+mod profiles;
+mod provenance;
+mod records;
+mod report;
+#[cfg(feature = "miette")]
+mod snippet;
+mod suggest;
+mod transform;
+mod warning;
+
+pub(crate) use field::FieldDeserializer;
+pub use include::IncludeLoader;
+pub use interpolate::{EnvResolver, FileResolver, Resolver, ResolverError};
+pub use layered::{Layered, DEFAULT_ORDINAL};
+pub use limits::Limits;
+pub use profiles::SpringLoader;
+pub use provenance::{Origin, Provenance};
+pub use records::Records;
+pub(crate) use report::validate;
+pub use report::{Diagnostic, Part, Report};
+#[cfg(feature = "miette")]
+pub use snippet::SourceReport;
+#[cfg(feature = "schema")]
+pub(crate) use suggest::suggest;
+pub use transform::{Transform, TransformError};
+pub use warning::Warning;
+
+use crate::env::EnvOverlay;
+
+/// Receives warnings instead of [`Deserializer::warnings`]
+type WarningHandler = Box<dyn Fn(&Warning) + Send + Sync>;
 
 /// Read properties from a stream
 ///
@@ -18,7 +57,39 @@ mod field;
 /// transforms a Java Properties file into a datastructure using
 /// the [`java-properties` crate](https://crates.io/crates/java-properties).
 pub struct Deserializer<R: io::Read> {
-    inner: PropertiesIter<R>,
+    inner: Lines<R>,
+    /// Whether reading the input has started
+    started: bool,
+    limits: Limits,
+    keys: usize,
+    recovery: Option<report::Recovery>,
+    warn_unknown_keys: bool,
+    warn_unused_keys: bool,
+    lenient: bool,
+    /// Malformed input found while reading, in lenient mode
+    malformed: Option<limits::Malformed>,
+    interpolate: bool,
+    resolvers: interpolate::Resolvers,
+    max_placeholder_depth: usize,
+    env_overlay: Option<EnvOverlay>,
+    profile: Option<String>,
+    /// Only keys with this prefix are read, without it
+    prefix: String,
+    #[cfg(feature = "jasypt")]
+    jasypt: Option<crate::jasypt::Jasypt>,
+    transforms: Vec<Box<dyn Transform + Send + Sync>>,
+    /// Keys that are only defined for inactive profiles, with those profiles
+    inactive: HashMap<String, Vec<String>>,
+    /// The number of `#---` document separators read so far
+    document: usize,
+    /// The fields of the top-level struct, once known
+    fields: Option<&'static [&'static str]>,
+    deprecated: Vec<deprecated::DeprecatedKey>,
+    /// The name of the input, for provenance
+    source_name: Option<Arc<str>>,
+    provenance: Option<Provenance>,
+    warnings: Vec<Warning>,
+    warning_handler: Option<WarningHandler>,
 }
 
 impl<R: io::Read> Deserializer<R> {
@@ -28,17 +99,700 @@ impl<R: io::Read> Deserializer<R> {
     /// expects *ISO-8859-1* by default. Use [`Deserializer::from_str`] instead, which
     /// sets the correct encoding.
     pub fn from_reader(reader: R) -> Self {
-        Self {
-            inner: PropertiesIter::new(reader),
-        }
+        Self::from_reader_with_encoding(reader, WINDOWS_1252)
     }
 
     /// Create a deserializer from a [`io::Read`] implementation and the specified encoding
     pub fn from_reader_with_encoding(reader: R, encoding: &'static Encoding) -> Self {
+        Self::with_lines(Lines::Pending(reader, encoding))
+    }
+
+    fn with_lines(inner: Lines<R>) -> Self {
         Self {
-            inner: PropertiesIter::new_with_encoding(reader, encoding),
+            inner,
+            started: false,
+            limits: Limits::default(),
+            keys: 0,
+            recovery: None,
+            warn_unknown_keys: false,
+            warn_unused_keys: false,
+            lenient: false,
+            malformed: None,
+            interpolate: false,
+            resolvers: Vec::new(),
+            max_placeholder_depth: interpolate::DEFAULT_MAX_DEPTH,
+            env_overlay: None,
+            profile: None,
+            prefix: String::new(),
+            #[cfg(feature = "jasypt")]
+            jasypt: None,
+            transforms: Vec::new(),
+            inactive: HashMap::new(),
+            document: 0,
+            fields: None,
+            deprecated: Vec::new(),
+            source_name: None,
+            provenance: None,
+            warnings: Vec::new(),
+            warning_handler: None,
+        }
+    }
+
+    /// Record a [`Warning::UnknownKey`] for every key that is not a field of the struct
+    ///
+    /// This is meant for structs that don't use `#[serde(deny_unknown_fields)]`, so that
+    /// typos don't go unnoticed. Keys that only match a `#[serde(alias)]` are reported too.
+    /// To access the warnings, deserialize from a `&mut Deserializer`.
+    pub fn set_warn_unknown_keys(&mut self, enabled: bool) {
+        self.warn_unknown_keys = enabled;
+    }
+
+    /// Record a [`Warning::UnusedKey`] for every key whose value was ignored
+    ///
+    /// Unlike [`Deserializer::set_warn_unknown_keys`], this doesn't compare keys
+    /// with the fields of the struct, but notes which values the struct skipped.
+    /// That way, keys that match a `#[serde(alias)]` are not reported. Keys collected by a `#[serde(flatten)]` field
+    /// count as used. See also [`crate::from_str_with_report`].
+    pub fn set_warn_unused_keys(&mut self, enabled: bool) {
+        self.warn_unused_keys = enabled;
+    }
+
+    /// Skip malformed lines instead of failing
+    ///
+    /// In lenient mode, malformed input is recorded as a [`Warning::MalformedLine`] and
+    /// the rest of the input is read as usual:
+    ///
+    /// - a line that can't be parsed, e.g. because of a bad `\uXXXX` escape, is skipped,
+    /// - byte sequences that are invalid in the selected encoding are replaced with
+    ///   `U+FFFD REPLACEMENT CHARACTER`,
+    /// - a line continuation at the end of the input is ignored. If the input doesn't
+    ///   end with a line break, the line with the continuation is skipped.
+    ///
+    /// Errors while reading from the underlying stream still fail. Without lenient mode,
+    /// invalid byte sequences are replaced and continuations at the end are ignored
+    /// silently.
+    pub fn set_lenient(&mut self, enabled: bool) {
+        self.lenient = enabled;
+    }
+
+    /// Resolve `${key}` placeholders in values
+    ///
+    /// When enabled, the whole input is read before deserializing, and every
+    /// placeholder is replaced with the value of the referenced key:
+    ///
+    /// - `${key}` is replaced with the value of `key`, which may be defined later in the input
+    /// - `${key:default}` uses `default` if `key` is not defined
+    /// - `$${` is an escaped, literal `${`
+    ///
+    /// If a key is defined more than once, the last definition is used. Placeholders in the
+    /// referenced values are resolved as well, and reference cycles fail with
+    /// [`Error::PlaceholderCycle`]. [`Limits::max_value_length`] applies to the resolved
+    /// values too, and placeholders may only be nested as deep as
+    /// [`Deserializer::set_max_placeholder_depth`] allows.
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// #
+    /// #[derive(Deserialize)]
+    /// struct Dirs {
+    ///     #[serde(rename = "log.dir")]
+    ///     log: String,
+    ///     #[serde(rename = "base.dir")]
+    ///     base: String,
+    /// }
+    ///
+    /// let text = "log.dir=${base.dir}/logs\nbase.dir=${app.home:/opt/app}";
+    /// let mut de = serde_java_properties::Deserializer::from_str(text);
+    /// de.set_interpolate(true);
+    /// let dirs = Dirs::deserialize(de).unwrap();
+    ///
+    /// assert_eq!(dirs.log, "/opt/app/logs");
+    /// ```
+    pub fn set_interpolate(&mut self, enabled: bool) {
+        self.interpolate = enabled;
+    }
+
+    /// Limit how deep placeholders may be nested, 64 levels by default
+    ///
+    /// Every placeholder that refers to another key, and every default that contains
+    /// placeholders, counts as a level. Deeper nesting fails with
+    /// [`Error::PlaceholderTooDeep`].
+    pub fn set_max_placeholder_depth(&mut self, depth: usize) {
+        self.max_placeholder_depth = depth;
+    }
+
+    /// Resolve placeholders starting with `prefix:` using `resolver`
+    ///
+    /// With `add_resolver("env", EnvResolver)`, the placeholder `${env:HOME}` is replaced
+    /// with the value of the `HOME` environment variable, or `${env:HOME:/root}` with
+    /// `/root` if it is not set. This enables placeholder interpolation, see
+    /// [`Deserializer::set_interpolate`].
+    ///
+    /// Resolvers can be closures, which is convenient for application specific sources
+    /// like a secret store:
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use serde::Deserialize;
+    /// use serde_java_properties::de::{Deserializer, EnvResolver, ResolverError};
+    ///
+    /// let mut de = Deserializer::from_str("password=${vault:db}\nuser=${env:DB_USER:app}");
+    /// de.add_resolver("env", EnvResolver);
+    /// de.add_resolver("vault", |name: &str| -> Result<Option<String>, ResolverError> {
+    ///     Ok(Some(format!("secret for {}", name)))
+    /// });
+    /// let map = HashMap::<String, String>::deserialize(de).unwrap();
+    ///
+    /// assert_eq!(map["password"], "secret for db");
+    /// ```
+    pub fn add_resolver<T>(&mut self, prefix: impl Into<String>, resolver: T)
+    where
+        T: Resolver + Send + Sync + 'static,
+    {
+        self.interpolate = true;
+        self.resolvers.push((prefix.into(), Box::new(resolver)));
+    }
+
+    /// Override values with environment variables
+    ///
+    /// A key is overridden by the variable named [`EnvOverlay::var_name`], e.g.
+    /// `MYAPP_DB_POOL_MAX` for `db.pool.max` with the prefix `MYAPP`. When deserializing
+    /// a struct, fields that are missing from the input are taken from the environment
+    /// as well. The overrides are applied before resolving placeholders.
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// use serde_java_properties::env::EnvOverlay;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Pool {
+    ///     #[serde(rename = "db.pool.max")]
+    ///     max: usize,
+    /// }
+    ///
+    /// let mut de = serde_java_properties::Deserializer::from_str("db.pool.max=10");
+    /// de.set_env_overlay(EnvOverlay::from_vars("MYAPP", [("MYAPP_DB_POOL_MAX", "20")]));
+    /// let pool = Pool::deserialize(de).unwrap();
+    ///
+    /// assert_eq!(pool.max, 20);
+    /// ```
+    pub fn set_env_overlay(&mut self, overlay: EnvOverlay) {
+        self.env_overlay = Some(overlay);
+    }
+
+    /// Select the active Quarkus style profile
+    ///
+    /// Keys prefixed with `%profile.` then take precedence over the same unprefixed
+    /// keys, and keys prefixed with other profiles are ignored. The whole input is read
+    /// before deserializing. If a required field is missing, but defined for other
+    /// profiles, deserialization fails with [`Error::MissingProfileField`].
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// #
+    /// #[derive(Deserialize)]
+    /// struct Db {
+    ///     #[serde(rename = "db.url")]
+    ///     url: String,
+    /// }
+    ///
+    /// let text = "db.url=jdbc:postgresql://db\n%dev.db.url=jdbc:h2:mem";
+    /// let mut de = serde_java_properties::Deserializer::from_str(text);
+    /// de.set_profile("dev");
+    /// let db = Db::deserialize(de).unwrap();
+    ///
+    /// assert_eq!(db.url, "jdbc:h2:mem");
+    /// ```
+    pub fn set_profile(&mut self, profile: impl Into<String>) {
+        self.profile = Some(profile.into());
+    }
+
+    /// Only read the keys that start with `prefix`, and remove it from them
+    ///
+    /// Other keys are skipped. Placeholders, environment variables and profiles still
+    /// refer to the full keys, and [`Transform`]s see the full keys as well.
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// #
+    /// #[derive(Deserialize)]
+    /// #[serde(deny_unknown_fields)]
+    /// struct DataSource {
+    ///     url: String,
+    /// }
+    ///
+    /// let text = "spring.datasource.url=jdbc:h2:mem\nkafka.consumer.group-id=app";
+    /// let mut de = serde_java_properties::Deserializer::from_str(text);
+    /// de.set_prefix("spring.datasource.");
+    /// let ds = DataSource::deserialize(de).unwrap();
+    ///
+    /// assert_eq!(ds.url, "jdbc:h2:mem");
+    /// ```
+    pub fn set_prefix(&mut self, prefix: impl Into<String>) {
+        self.prefix = prefix.into();
+    }
+
+    /// Decrypt Jasypt `ENC(...)` values
+    ///
+    /// Encrypted values are decrypted before they are parsed, and after placeholders
+    /// are resolved. Values that fail to decrypt fail with [`Error::Decryption`].
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// use serde_java_properties::jasypt::{Algorithm, Jasypt};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Db {
+    ///     password: String,
+    /// }
+    ///
+    /// let jasypt = Jasypt::new(Algorithm::PbeWithHmacSha512AndAes256, "secret");
+    /// let text = format!("password={}", jasypt.encrypt("hunter2").unwrap());
+    ///
+    /// let mut de = serde_java_properties::Deserializer::from_str(&text);
+    /// de.set_jasypt(jasypt);
+    /// let db = Db::deserialize(de).unwrap();
+    ///
+    /// assert_eq!(db.password, "hunter2");
+    /// ```
+    #[cfg(feature = "jasypt")]
+    pub fn set_jasypt(&mut self, jasypt: crate::jasypt::Jasypt) {
+        self.jasypt = Some(jasypt);
+    }
+
+    /// Rewrite every value with `transform` before it is parsed
+    ///
+    /// Transforms run in the order they were added, after placeholders are resolved and
+    /// encrypted values are decrypted. They also get the value as read, see
+    /// [`Transform::transform`]. An error fails deserialization with [`Error::Transform`].
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// use serde_java_properties::de::{Deserializer, TransformError};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Limits {
+    ///     max: Option<usize>,
+    /// }
+    ///
+    /// let mut de = Deserializer::from_str("max=N/A");
+    /// de.add_transform(|_: &str, _: &str, value: &str, _| -> Result<String, TransformError> {
+    ///     Ok(if value == "N/A" { String::new() } else { value.to_string() })
+    /// });
+    /// let limits = Limits::deserialize(de).unwrap();
+    ///
+    /// assert_eq!(limits.max, None);
+    /// ```
+    pub fn add_transform<T>(&mut self, transform: T)
+    where
+        T: Transform + Send + Sync + 'static,
+    {
+        self.transforms.push(Box::new(transform));
+    }
+
+    /// Set limits on the size of the input
+    ///
+    /// This should be called before deserializing. When a limit is exceeded,
+    /// deserialization fails with the corresponding [`Error`] variant.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Accept `old` as a deprecated name of the key `new`
+    ///
+    /// Every use of `old` is recorded as a [`Warning::DeprecatedKey`] with `message`,
+    /// and deserialization fails with [`Error::DeprecatedKeyConflict`] if both keys are
+    /// set. Unlike `#[serde(alias)]`, this makes it visible which configuration files
+    /// still need to be migrated. Keys are matched before a prefix is removed.
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// # use serde_java_properties::{de::Warning, Deserializer};
+    /// #
+    /// #[derive(Deserialize)]
+    /// struct Pool {
+    ///     #[serde(rename = "db.pool.max")]
+    ///     max: usize,
+    /// }
+    ///
+    /// let mut de = Deserializer::from_str("db.maxconn=20");
+    /// de.add_deprecated_key("db.maxconn", "db.pool.max", "it will be removed in 3.0");
+    /// let pool = Pool::deserialize(&mut de)?;
+    /// let warnings = de.take_warnings();
+    ///
+    /// assert_eq!(pool.max, 20);
+    /// assert!(matches!(
+    ///     &warnings[..],
+    ///     [Warning::DeprecatedKey { key, line: 1, .. }] if key == "db.maxconn"
+    /// ));
+    /// # Ok::<(), serde_java_properties::de::Error>(())
+    /// ```
+    pub fn add_deprecated_key(
+        &mut self,
+        old: impl Into<String>,
+        new: impl Into<String>,
+        message: impl Into<String>,
+    ) {
+        self.deprecated.push(deprecated::DeprecatedKey {
+            old: old.into(),
+            new: new.into(),
+            message: message.into(),
+        });
+    }
+
+    /// Pass every warning to `handler` as it is recorded, instead of collecting it
+    ///
+    /// This makes warnings available when deserializing from an owned
+    /// `Deserializer`, e.g. to log them.
+    pub fn set_warning_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&Warning) + Send + Sync + 'static,
+    {
+        self.warning_handler = Some(Box::new(handler));
+    }
+
+    /// Record a warning, or pass it to the handler
+    fn warn(&mut self, warning: Warning) {
+        match &self.warning_handler {
+            Some(handler) => handler(&warning),
+            None => self.warnings.push(warning),
         }
     }
+
+    /// Name the input, e.g. after its file, for [`Origin::Source`]
+    ///
+    /// [`Layered`] and the loaders name the files they read after their paths.
+    pub fn set_source_name(&mut self, name: impl Into<String>) {
+        self.source_name = Some(name.into().into());
+    }
+
+    /// Record where the value of every key came from
+    ///
+    /// After deserializing from a `&mut Deserializer`, [`Deserializer::provenance`]
+    /// returns the source, line number and raw text of every key that was read, and
+    /// marks the fields of the top-level struct that were not set as defaulted. See
+    /// [`Provenance`].
+    pub fn set_track_provenance(&mut self, enabled: bool) {
+        self.provenance = enabled.then(Provenance::default);
+    }
+
+    /// The provenance recorded so far, if it is tracked
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    /// The warnings recorded so far
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Take the warnings recorded so far, leaving an empty list
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Read the groups of keys like `job.1.name` and `job.1.cron` as records
+    ///
+    /// Every key that starts with `prefix` is split into the id of its record and a
+    /// field, at the first `.` after the prefix. Keys without the prefix are skipped.
+    /// A record is deserialized as soon as a key of another record is read, so the
+    /// whole input is never held in memory. This requires the keys of each record to
+    /// be contiguous, with only keys without the prefix between them: a key for a
+    /// record that already ended yields an [`Error::SplitRecord`] instead of being
+    /// added to it. See [`Records`].
+    ///
+    /// ```
+    /// # use serde::Deserialize;
+    /// #[derive(Debug, Deserialize)]
+    /// struct Job {
+    ///     name: String,
+    ///     cron: String,
+    /// }
+    ///
+    /// let text = "job.1.name=backup\njob.1.cron=0 3 * * *\njob.2.name=report";
+    /// let de = serde_java_properties::Deserializer::from_str(text);
+    /// let mut jobs = de.into_records::<Job>("job.");
+    ///
+    /// let job = jobs.next().unwrap()?;
+    /// assert_eq!((job.name.as_str(), job.cron.as_str()), ("backup", "0 3 * * *"));
+    /// let err = jobs.next().unwrap().unwrap_err();
+    /// assert_eq!(err.to_string(), "record `job.2.*`: missing field `cron`");
+    /// assert!(jobs.next().is_none());
+    /// # Ok::<(), serde_java_properties::de::Error>(())
+    /// ```
+    pub fn into_records<T: DeserializeOwned>(self, prefix: impl Into<String>) -> Records<R, T> {
+        Records::new(self, prefix.into())
+    }
+}
+
+/// The lines of the input, which start being read on first use
+enum Lines<R: io::Read> {
+    Pending(R, &'static Encoding),
+    Reading(Box<PropertiesIter<limits::LimitedReader<R>>>),
+    /// All entries were read up front, e.g. to resolve placeholders
+    Buffered(std::vec::IntoIter<Entry>),
+    Empty,
+}
+
+/// A key-value pair from the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Entry {
+    /// The 1-based line number of the key, or 0 if it doesn't come from the input
+    pub line: usize,
+    pub key: String,
+    pub value: String,
+    /// The name of the source, if known
+    pub source: Option<Arc<str>>,
+    /// The value before it was changed, if provenance is tracked
+    pub raw: Option<String>,
+}
+
+impl<R: io::Read> Deserializer<R> {
+    /// Read the next key-value pair, skipping entries that fail to read while validating
+    fn next_entry(&mut self) -> Result<Option<Entry>, Error> {
+        loop {
+            let error = match self.read_next_entry() {
+                Err(error) if self.recovery.is_some() => error,
+                result => return result,
+            };
+            let (line, key, part) = match &error {
+                Error::Properties(e) if !is_io_error(e) => match e.line_number() {
+                    Some(line) => (line, None, None),
+                    None => return Err(error),
+                },
+                Error::Transform { key, line, .. } => (*line, Some(key.clone()), Some(Part::Value)),
+                #[cfg(feature = "jasypt")]
+                Error::Decryption { key, line, .. } => {
+                    (*line, Some(key.clone()), Some(Part::Value))
+                }
+                _ => return Err(error),
+            };
+            let recovery = self.recovery.as_mut().unwrap();
+            // Later runs read the same input again
+            if recovery.skip.insert(line) {
+                recovery.diagnostics.push(Diagnostic {
+                    key,
+                    line: Some(line),
+                    part,
+                    error,
+                });
+            }
+        }
+    }
+
+    /// Read the next key-value pair
+    fn read_next_entry(&mut self) -> Result<Option<Entry>, Error> {
+        if !self.started {
+            self.started = true;
+            self.prepare()?;
+        }
+        let mut entry = loop {
+            match self.read_entry()? {
+                Some(entry) if !entry.key.starts_with(&self.prefix) => continue,
+                entry => break entry,
+            }
+        };
+        if let (true, Some(entry)) = (self.keeps_raw(), &mut entry) {
+            entry.raw.get_or_insert_with(|| entry.value.clone());
+        }
+        #[cfg(feature = "jasypt")]
+        if let (Some(jasypt), Some(entry)) = (&self.jasypt, &mut entry) {
+            match jasypt.decrypt_value(&entry.value) {
+                Ok(Some(value)) => entry.value = value,
+                Ok(None) => {}
+                Err(source) => {
+                    return Err(Error::Decryption {
+                        key: entry.key.clone(),
+                        line: entry.line,
+                        source,
+                    })
+                }
+            }
+        }
+        if let Some(entry) = &mut entry {
+            for transform in &self.transforms {
+                let raw = entry.raw.as_deref().unwrap_or_default();
+                entry.value = transform
+                    .transform(&entry.key, raw, &entry.value, entry.line)
+                    .map_err(|source| Error::Transform {
+                        key: entry.key.clone(),
+                        line: entry.line,
+                        source,
+                    })?;
+            }
+            entry.key.drain(..self.prefix.len());
+            if let Some(provenance) = &mut self.provenance {
+                let origin = Origin::Source {
+                    name: entry.source.as_deref().map(str::to_string),
+                    line: entry.line,
+                    raw: entry.raw.clone().unwrap_or_default(),
+                };
+                provenance.record(&entry.key, origin);
+            }
+        }
+        Ok(entry)
+    }
+
+    /// Whether entries keep their value as read, for provenance and transforms
+    fn keeps_raw(&self) -> bool {
+        self.provenance.is_some() || !self.transforms.is_empty()
+    }
+
+    /// Start reading, and apply the options that need the whole input up front
+    fn prepare(&mut self) -> Result<(), Error> {
+        if let Lines::Pending(..) = self.inner {
+            if let Lines::Pending(reader, encoding) =
+                std::mem::replace(&mut self.inner, Lines::Empty)
+            {
+                let mut reader = limits::LimitedReader::new(reader, self.limits);
+                if self.lenient {
+                    let malformed = limits::Malformed::default();
+                    reader.check_malformed(encoding, malformed.clone());
+                    self.malformed = Some(malformed);
+                }
+                self.inner = Lines::Reading(Box::new(PropertiesIter::new_with_encoding(
+                    reader, encoding,
+                )));
+            }
+        }
+        if self.interpolate
+            || self.env_overlay.is_some()
+            || self.profile.is_some()
+            || !self.deprecated.is_empty()
+        {
+            let mut entries = Vec::new();
+            while let Some(mut entry) = self.read_entry()? {
+                if self.keeps_raw() {
+                    entry.raw.get_or_insert_with(|| entry.value.clone());
+                }
+                entries.push(entry);
+            }
+            if let Some(profile) = &self.profile {
+                self.inactive = profiles::select_profile(&mut entries, profile);
+            }
+            for warning in deprecated::rename(&mut entries, &self.deprecated)? {
+                self.warn(warning);
+            }
+            if let Some(overlay) = &self.env_overlay {
+                let fields: Vec<String> = (self.fields.unwrap_or_default().iter())
+                    .map(|field| format!("{}{}", self.prefix, field))
+                    .collect();
+                overlay.apply(&mut entries, &fields);
+            }
+            if self.interpolate {
+                interpolate::interpolate(
+                    &mut entries,
+                    &self.resolvers,
+                    self.max_placeholder_depth,
+                    self.limits.max_value_length,
+                )?;
+            }
+            self.inner = Lines::Buffered(entries.into_iter());
+        }
+        Ok(())
+    }
+
+    /// Read all remaining key-value pairs
+    pub(crate) fn into_entries(mut self) -> Result<Vec<Entry>, Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = self.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Read all remaining key-value pairs, split into documents at `#---` lines
+    pub(crate) fn into_documents(mut self) -> Result<Vec<Vec<Entry>>, Error> {
+        let mut documents = vec![Vec::new()];
+        while let Some(entry) = self.next_entry()? {
+            documents.resize_with(self.document + 1, Vec::new);
+            documents[self.document].push(entry);
+        }
+        Ok(documents)
+    }
+
+    /// Read the next key-value pair from the input, skipping malformed lines in lenient mode
+    fn read_entry(&mut self) -> Result<Option<Entry>, Error> {
+        loop {
+            let inner = match &mut self.inner {
+                Lines::Reading(inner) => inner,
+                Lines::Buffered(entries) => return Ok(entries.next()),
+                _ => return Ok(None),
+            };
+            let line = match inner.next() {
+                None => {
+                    self.warn_malformed(usize::MAX);
+                    return Ok(None);
+                }
+                Some(Ok(line)) => line,
+                Some(Err(e)) => match e.line_number() {
+                    _ if is_io_error(&e) => return Err(Error::from_io(e)),
+                    Some(line) if self.lenient => {
+                        self.warn_malformed(line);
+                        let message = format!("{}, the line was skipped", e);
+                        self.warn(Warning::MalformedLine { line, message });
+                        continue;
+                    }
+                    _ => return Err(e.into()),
+                },
+            };
+            let line_number = line.line_number();
+            self.warn_malformed(line_number);
+            let (key, value) = match line.consume_content() {
+                KVPair(key, value) => (key, value),
+                Comment(text) if text.trim_end() == "---" => {
+                    self.document += 1;
+                    continue;
+                }
+                Comment(_) => continue,
+            };
+            self.keys += 1;
+            if let Some(limit) = self.limits.max_keys {
+                if self.keys > limit {
+                    return Err(Error::TooManyKeys { limit });
+                }
+            }
+            if let Some(limit) = self.limits.max_value_length {
+                if value.len() > limit {
+                    let line = line_number;
+                    return Err(Error::ValueTooLong { line, limit });
+                }
+            }
+            return Ok(Some(Entry {
+                line: line_number,
+                key,
+                value,
+                source: self.source_name.clone(),
+                raw: None,
+            }));
+        }
+    }
+
+    /// Report the malformed input that was found up to `line`
+    fn warn_malformed(&mut self, line: usize) {
+        let Some(malformed) = &self.malformed else {
+            return;
+        };
+        let found: Vec<_> = {
+            let mut malformed = malformed.lock().unwrap();
+            let end = malformed.partition_point(|(at, _)| *at <= line);
+            malformed.drain(..end).collect()
+        };
+        for (line, message) in found {
+            self.warn(Warning::MalformedLine { line, message });
+        }
+    }
+}
+
+fn is_io_error(e: &java_properties::PropertiesError) -> bool {
+    std::error::Error::source(e).is_some_and(|cause| cause.is::<io::Error>())
+}
+
+impl Deserializer<io::Empty> {
+    /// Create a deserializer from key-value pairs that were already read
+    pub(crate) fn from_entries(entries: Vec<Entry>) -> Self {
+        Self::with_lines(Lines::Buffered(entries.into_iter()))
+    }
 }
 
 impl<'a> Deserializer<io::Cursor<&'a str>> {
@@ -77,21 +831,353 @@ pub enum Error {
     /// A line failed to load
     Properties(java_properties::PropertiesError),
     /// A field with type hint integer failed to parse
+    ///
+    /// Deserializing reports this as the `source` of an [`Error::InvalidValue`].
     ParseIntError(ParseIntError),
     /// A field with type hint float failed to parse
+    ///
+    /// Deserializing reports this as the `source` of an [`Error::InvalidValue`].
     ParseFloatError(ParseFloatError),
     /// A field with type hint float failed to parse
+    ///
+    /// Deserializing reports this as the `source` of an [`Error::InvalidValue`].
     ParseBoolError(ParseBoolError),
+    /// A value could not be parsed as the type of its field
+    InvalidValue {
+        /// The value as found in the input
+        value: String,
+        /// A description of the accepted values
+        expected: &'static str,
+        /// The key of the value, if known
+        key: Option<String>,
+        /// The 1-based line number of the value, if known
+        line: Option<usize>,
+        /// The underlying parse error
+        source: Box<Error>,
+    },
+    /// A key that doesn't match any field of the struct
+    UnknownField {
+        /// The key as found in the input
+        field: String,
+        /// The 1-based line number of the key, if known
+        line: Option<usize>,
+        /// A field with a similar name
+        suggestion: Option<String>,
+    },
+    /// A physical line was longer than [`Limits::max_line_length`]
+    LineTooLong {
+        /// The 1-based line number
+        line: usize,
+        /// The limit in bytes
+        limit: usize,
+    },
+    /// A value was longer than [`Limits::max_value_length`]
+    ValueTooLong {
+        /// The 1-based line number of the key
+        line: usize,
+        /// The limit in bytes
+        limit: usize,
+    },
+    /// A line was followed by more than [`Limits::max_continuation_lines`] continuation lines
+    TooManyContinuationLines {
+        /// The 1-based line number of the first continuation line over the limit
+        line: usize,
+        /// The limit
+        limit: usize,
+    },
+    /// The input had more than [`Limits::max_keys`] key-value pairs
+    TooManyKeys {
+        /// The limit
+        limit: usize,
+    },
+    /// The input was larger than [`Limits::max_total_bytes`]
+    InputTooLarge {
+        /// The limit in bytes
+        limit: usize,
+    },
+    /// A placeholder refers to a key that is not defined, and has no default
+    UnresolvedPlaceholder {
+        /// The key whose value contains the placeholder
+        key: String,
+        /// The 1-based line number of that key
+        line: usize,
+        /// The placeholder, e.g. `${base.dir}`
+        placeholder: String,
+    },
+    /// A [`Resolver`] failed to look up a placeholder
+    ResolverFailed {
+        /// The key whose value contains the placeholder
+        key: String,
+        /// The 1-based line number of that key
+        line: usize,
+        /// The placeholder, e.g. `${file:/run/secrets/db}`
+        placeholder: String,
+        /// The error from the resolver
+        source: ResolverError,
+    },
+    /// Placeholders that refer to each other in a cycle
+    PlaceholderCycle {
+        /// The keys in the cycle with their 1-based line numbers, in order of reference.
+        /// The first key is repeated at the end.
+        chain: Vec<(String, usize)>,
+    },
+    /// Placeholders were nested deeper than [`Deserializer::set_max_placeholder_depth`]
+    PlaceholderTooDeep {
+        /// The key whose value was being resolved at the limit
+        key: String,
+        /// The 1-based line number of the key
+        line: usize,
+        /// The maximum depth
+        limit: usize,
+    },
+    /// A [`Transform`] rejected a value
+    Transform {
+        /// The key of the value
+        key: String,
+        /// The 1-based line number of the value
+        line: usize,
+        /// The cause
+        source: TransformError,
+    },
+    /// An `ENC(...)` value could not be decrypted
+    #[cfg(feature = "jasypt")]
+    Decryption {
+        /// The key of the value
+        key: String,
+        /// The 1-based line number of the value
+        line: usize,
+        /// The cause
+        source: crate::jasypt::Error,
+    },
+    /// An I/O error outside of parsing, e.g. when opening a file
+    Io(io::Error),
+    /// An error in a specific file
+    File {
+        /// The path of the file
+        path: PathBuf,
+        /// The error
+        source: Box<Error>,
+    },
+    /// Files that include each other in a cycle
+    IncludeCycle {
+        /// The files in the cycle, in order of inclusion. The first file is repeated at the end.
+        chain: Vec<PathBuf>,
+    },
+    /// An included file is outside of the root directory
+    IncludeOutsideRoot {
+        /// The resolved path of the file
+        path: PathBuf,
+        /// The canonical path of the root directory
+        root: PathBuf,
+    },
+    /// A profile expression in `spring.config.activate.on-profile` is malformed
+    InvalidProfileExpression {
+        /// The expression
+        expression: String,
+        /// The 1-based line number of the expression
+        line: usize,
+    },
+    /// A required field is only defined for inactive profiles
+    MissingProfileField {
+        /// The name of the field
+        field: &'static str,
+        /// The active profile
+        profile: String,
+        /// The profiles that define the field
+        profiles: Vec<String>,
+    },
+    /// A key and its deprecated name are both set
+    ///
+    /// See [`Deserializer::add_deprecated_key`]
+    DeprecatedKeyConflict {
+        /// The deprecated key
+        old: String,
+        /// The 1-based line number of the deprecated key
+        old_line: usize,
+        /// The key that replaces it
+        new: String,
+        /// The 1-based line number of the new key
+        new_line: usize,
+    },
+    /// A record could not be read
+    ///
+    /// See [`Deserializer::into_records`]
+    Record {
+        /// The prefix of the record's keys, e.g. `job.2.`
+        prefix: String,
+        /// The error
+        source: Box<Error>,
+    },
+    /// A key belongs to a record whose keys ended on an earlier line
+    ///
+    /// See [`Deserializer::into_records`]
+    SplitRecord {
+        /// The key
+        key: String,
+        /// The 1-based line number of the key
+        line: usize,
+    },
+    /// A number, or the length of a value, is out of the range of a schema
+    ///
+    /// See [`crate::schema`]
+    OutOfRange {
+        /// The value as found in the input
+        value: String,
+        /// The inclusive lower bound
+        min: Option<f64>,
+        /// The inclusive upper bound
+        max: Option<f64>,
+        /// Whether the bounds apply to the length of the value, not to a number
+        length: bool,
+    },
+    /// A value doesn't match the pattern of a schema
+    ///
+    /// See [`crate::schema`]
+    PatternMismatch {
+        /// The value as found in the input
+        value: String,
+        /// The regular expression
+        pattern: String,
+    },
+    /// A value is not one of the choices of a schema
+    ///
+    /// See [`crate::schema`]
+    InvalidChoice {
+        /// The value as found in the input
+        value: String,
+        /// The allowed values
+        choices: Vec<String>,
+    },
+    /// A key that a schema requires was not present in the input
+    ///
+    /// See [`crate::schema`]
+    MissingKey {
+        /// The key
+        key: String,
+    },
+    /// A required field was not present in the input
+    MissingField {
+        /// The name of the field
+        field: &'static str,
+    },
     /// Not supported
     NotSupported,
 }
 
+impl Error {
+    /// Attach the path of the file that this error happened in
+    fn in_file(path: &Path, e: impl Into<Error>) -> Self {
+        Self::File {
+            path: path.to_owned(),
+            source: Box::new(e.into()),
+        }
+    }
+
+    /// Whether this is an error for a file that doesn't exist
+    fn is_not_found(&self) -> bool {
+        match self {
+            Self::Io(e) => e.kind() == io::ErrorKind::NotFound,
+            Self::File { source, .. } => source.is_not_found(),
+            _ => false,
+        }
+    }
+
+    /// The 1-based line number that this error refers to, if known
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Self::Properties(e) => e.line_number(),
+            Self::UnknownField { line, .. } | Self::InvalidValue { line, .. } => *line,
+            Self::UnresolvedPlaceholder { line, .. }
+            | Self::ResolverFailed { line, .. }
+            | Self::InvalidProfileExpression { line, .. }
+            | Self::DeprecatedKeyConflict { old_line: line, .. }
+            | Self::SplitRecord { line, .. }
+            | Self::Transform { line, .. } => Some(*line),
+            Self::PlaceholderCycle { chain } => chain.first().map(|(_, line)| *line),
+            Self::LineTooLong { line, .. }
+            | Self::ValueTooLong { line, .. }
+            | Self::TooManyContinuationLines { line, .. }
+            | Self::PlaceholderTooDeep { line, .. } => Some(*line),
+            #[cfg(feature = "jasypt")]
+            Self::Decryption { line, .. } => Some(*line),
+            Self::Record { source, .. } | Self::File { source, .. } => source.line(),
+            _ => None,
+        }
+    }
+
+    /// Explain a missing field that is only defined for inactive profiles
+    fn for_profile<R: io::Read>(self, de: &Deserializer<R>) -> Self {
+        let (Self::MissingField { field }, Some(profile)) = (&self, &de.profile) else {
+            return self;
+        };
+        match de.inactive.get(&format!("{}{}", de.prefix, field)) {
+            Some(profiles) => Self::MissingProfileField {
+                field,
+                profile: profile.clone(),
+                profiles: profiles.clone(),
+            },
+            None => self,
+        }
+    }
+
+    /// Attach a line number to errors that can carry one
+    fn at_line(mut self, line_number: usize) -> Self {
+        match &mut self {
+            // Entries that don't come from the input have no line
+            _ if line_number == 0 => {}
+            Self::UnknownField { line, .. } | Self::InvalidValue { line, .. } => {
+                line.get_or_insert(line_number);
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Attach the key and line number of a value to errors that can carry them
+    fn at_value(mut self, key: &str, line_number: usize) -> Self {
+        if let Self::InvalidValue { key: k, .. } = &mut self {
+            k.get_or_insert_with(|| key.to_string());
+        }
+        self.at_line(line_number)
+    }
+}
+
+impl Error {
+    /// Convert an I/O error, unwrapping limits that were exceeded while reading
+    fn from_io(e: java_properties::PropertiesError) -> Self {
+        let exceeded = std::error::Error::source(&e)
+            .and_then(|cause| cause.downcast_ref::<io::Error>())
+            .and_then(|cause| cause.get_ref())
+            .and_then(|cause| cause.downcast_ref::<limits::Exceeded>());
+        match exceeded {
+            Some(limits::Exceeded::LineLength { line, limit }) => Self::LineTooLong {
+                line: *line,
+                limit: *limit,
+            },
+            Some(limits::Exceeded::ContinuationLines { line, limit }) => {
+                Self::TooManyContinuationLines {
+                    line: *line,
+                    limit: *limit,
+                }
+            }
+            Some(limits::Exceeded::TotalBytes { limit }) => Self::InputTooLarge { limit: *limit },
+            None => Self::Properties(e),
+        }
+    }
+}
+
 impl From<java_properties::PropertiesError> for Error {
     fn from(e: java_properties::PropertiesError) -> Self {
         Self::Properties(e)
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
 impl From<ParseIntError> for Error {
     fn from(e: ParseIntError) -> Self {
         Self::ParseIntError(e)
@@ -114,6 +1200,177 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Custom { msg } => write!(f, "Custom: {:?}", msg),
+            Self::InvalidValue {
+                value,
+                key,
+                line,
+                source,
+                ..
+            } => {
+                write!(f, "invalid value {:?}", value)?;
+                if let Some(key) = key {
+                    write!(f, " for `{}`", key)?;
+                }
+                if let Some(line) = line {
+                    write!(f, " on line {}", line)?;
+                }
+                write!(f, ": {}", source)
+            }
+            Self::UnknownField {
+                field,
+                line,
+                suggestion,
+            } => {
+                write!(f, "unknown field `{}`", field)?;
+                if let Some(line) = line {
+                    write!(f, " on line {}", line)?;
+                }
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean `{}`?", suggestion)?;
+                }
+                Ok(())
+            }
+            Self::LineTooLong { line, limit } => {
+                write!(f, "line {} is longer than {} bytes", line, limit)
+            }
+            Self::ValueTooLong { line, limit } => {
+                write!(f, "value on line {} is longer than {} bytes", line, limit)
+            }
+            Self::TooManyContinuationLines { line, limit } => {
+                write!(f, "more than {} continuation lines on line {}", limit, line)
+            }
+            Self::TooManyKeys { limit } => write!(f, "more than {} keys", limit),
+            Self::InputTooLarge { limit } => write!(f, "input is larger than {} bytes", limit),
+            Self::UnresolvedPlaceholder {
+                key,
+                line,
+                placeholder,
+            } => write!(
+                f,
+                "unresolved placeholder {} in `{}` on line {}",
+                placeholder, key, line
+            ),
+            Self::ResolverFailed {
+                key,
+                line,
+                placeholder,
+                source,
+            } => write!(
+                f,
+                "failed to resolve {} in `{}` on line {}: {}",
+                placeholder, key, line, source
+            ),
+            Self::PlaceholderCycle { chain } => {
+                write!(f, "placeholder cycle: ")?;
+                for (i, (key, line)) in chain.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " -> ")?;
+                    }
+                    write!(f, "`{}` (line {})", key, line)?;
+                }
+                Ok(())
+            }
+            Self::PlaceholderTooDeep { key, line, limit } => write!(
+                f,
+                "placeholders in `{}` on line {} are nested deeper than {} levels",
+                key, line, limit
+            ),
+            Self::Transform { key, line, source } => {
+                write!(
+                    f,
+                    "failed to transform `{}` on line {}: {}",
+                    key, line, source
+                )
+            }
+            #[cfg(feature = "jasypt")]
+            Self::Decryption { key, line, source } => {
+                write!(
+                    f,
+                    "failed to decrypt `{}` on line {}: {}",
+                    key, line, source
+                )
+            }
+            Self::Io(e) => e.fmt(f),
+            Self::File { path, source } => write!(f, "{}: {}", path.display(), source),
+            Self::Record { prefix, source } => write!(f, "record `{}*`: {}", prefix, source),
+            Self::SplitRecord { key, line } => write!(
+                f,
+                "`{}` on line {} belongs to a record that ended before",
+                key, line
+            ),
+            Self::IncludeCycle { chain } => {
+                write!(f, "include cycle: ")?;
+                for (i, path) in chain.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " -> ")?;
+                    }
+                    write!(f, "{}", path.display())?;
+                }
+                Ok(())
+            }
+            Self::IncludeOutsideRoot { path, root } => write!(
+                f,
+                "{} is outside of the root directory {}",
+                path.display(),
+                root.display()
+            ),
+            Self::InvalidProfileExpression { expression, line } => write!(
+                f,
+                "invalid profile expression `{}` on line {}",
+                expression, line
+            ),
+            Self::MissingProfileField {
+                field,
+                profile,
+                profiles,
+            } => write!(
+                f,
+                "missing field `{}` for profile `{}`, it is only defined for `%{}`",
+                field,
+                profile,
+                profiles.join("`, `%")
+            ),
+            Self::DeprecatedKeyConflict {
+                old,
+                old_line,
+                new,
+                new_line,
+            } => write!(
+                f,
+                "`{}` on line {} is a deprecated name of `{}` on line {}, only one of them may be set",
+                old, old_line, new, new_line
+            ),
+            Self::OutOfRange {
+                value,
+                min,
+                max,
+                length,
+            } => {
+                write!(f, "invalid value `{}`, expected ", value)?;
+                match (min, max) {
+                    (Some(min), Some(max)) => write!(f, "between {} and {}", min, max)?,
+                    (Some(min), None) => write!(f, "at least {}", min)?,
+                    (None, Some(max)) => write!(f, "at most {}", max)?,
+                    (None, None) => write!(f, "any value")?,
+                }
+                if *length {
+                    write!(f, " characters")?;
+                }
+                Ok(())
+            }
+            Self::PatternMismatch { value, pattern } => write!(
+                f,
+                "invalid value `{}`, expected a match of `{}`",
+                value, pattern
+            ),
+            Self::InvalidChoice { value, choices } => write!(
+                f,
+                "invalid value `{}`, expected one of `{}`",
+                value,
+                choices.join("`, `")
+            ),
+            Self::MissingKey { key } => write!(f, "missing key `{}`", key),
+            Self::MissingField { field } => write!(f, "missing field `{}`", field),
             Self::NotSupported => write!(f, "Not supported"),
             Self::Properties(e) => e.fmt(f),
             Self::ParseIntError(e) => e.fmt(f),
@@ -123,7 +1380,21 @@ impl fmt::Display for Error {
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidValue { source, .. } => Some(source.as_ref()),
+            Self::ResolverFailed { source, .. } => Some(source.as_ref()),
+            Self::Transform { source, .. } => Some(source.as_ref()),
+            #[cfg(feature = "jasypt")]
+            Self::Decryption { source, .. } => Some(source),
+            Self::Io(e) => Some(e),
+            Self::File { source, .. } => Some(source.as_ref()),
+            Self::Record { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl serde::de::Error for Error {
     fn custom<T>(msg: T) -> Self
@@ -134,48 +1405,169 @@ impl serde::de::Error for Error {
             msg: msg.to_string(),
         }
     }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        Self::UnknownField {
+            field: field.to_string(),
+            line: None,
+            suggestion: suggest::suggest(field, expected.iter().copied()).map(str::to_string),
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self::MissingField { field }
+    }
 }
 
 impl<'de, I: io::Read> de::Deserializer<'de> for Deserializer<I> {
     type Error = Error;
 
+    fn deserialize_any<V>(mut self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        (&mut self).deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        mut self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        (&mut self).deserialize_struct(name, fields, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+impl<'de, I: io::Read> de::Deserializer<'de> for &mut Deserializer<I> {
+    type Error = Error;
+
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_map(PropertiesMapAccess {
-            de: self,
-            line_value: None,
-        })
+        visitor
+            .visit_map(PropertiesMapAccess {
+                de: &mut *self,
+                pending: None,
+                fields: None,
+                seen: HashSet::new(),
+            })
+            .map_err(|e| e.for_profile(self))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let top_level = !self.started;
+        if top_level {
+            self.fields = Some(fields);
+        }
+        let value = visitor
+            .visit_map(PropertiesMapAccess {
+                de: &mut *self,
+                pending: None,
+                fields: Some(fields),
+                seen: HashSet::new(),
+            })
+            .map_err(|e| e.for_profile(self))?;
+        if let Some(provenance) = self.provenance.as_mut().filter(|_| top_level) {
+            for field in fields {
+                if provenance.get(field).is_none() {
+                    provenance.record(field, Origin::Defaulted);
+                }
+            }
+        }
+        Ok(value)
     }
 
     forward_to_deserialize_any! {
         bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
         bytes byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum identifier ignored_any
+        tuple_struct map enum identifier ignored_any
     }
 }
 
-struct PropertiesMapAccess<I: io::Read> {
-    de: Deserializer<I>,
-    line_value: Option<String>,
+/// The value belonging to the most recently returned key
+enum Pending {
+    Entry(Entry),
+    Placeholder,
+}
+
+struct PropertiesMapAccess<'a, I: io::Read> {
+    de: &'a mut Deserializer<I>,
+    pending: Option<Pending>,
+    /// The fields of the struct being deserialized
+    fields: Option<&'static [&'static str]>,
+    /// The fields seen so far, while validating
+    seen: HashSet<&'static str>,
 }
 
-impl<'de, I: io::Read> MapAccess<'de> for PropertiesMapAccess<I> {
+impl<'de, 'a, I: io::Read> MapAccess<'de> for PropertiesMapAccess<'a, I> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
     where
         K: serde::de::DeserializeSeed<'de>,
     {
-        while let Some(line) = self.de.inner.next().transpose()? {
-            match line.consume_content() {
-                Comment(_) => {} // ignore
-                KVPair(key, value) => {
-                    self.line_value = Some(value);
-                    return seed.deserialize(key.into_deserializer()).map(Some);
+        while let Some(entry) = self.de.next_entry()? {
+            if let Some(recovery) = &mut self.de.recovery {
+                if recovery.skip.contains(&entry.line) {
+                    continue;
                 }
-            };
+                // A struct's visitor rejects a field that it already saw
+                let field = (self.fields.unwrap_or_default().iter()).find(|f| **f == entry.key);
+                if let Some(field) = field.filter(|field| !self.seen.insert(field)) {
+                    recovery.skip.insert(entry.line);
+                    let error = de::Error::duplicate_field(field);
+                    recovery.recovered(entry.line, entry.key, Part::Key, error);
+                    continue;
+                }
+                recovery.failed = Some(report::Failure::Key {
+                    line: entry.line,
+                    key: entry.key.clone(),
+                });
+            }
+            let k = seed
+                .deserialize(IntoDeserializer::<Error>::into_deserializer(
+                    entry.key.as_str(),
+                ))
+                .map_err(|e| e.at_line(entry.line))?;
+            if let Some(recovery) = &mut self.de.recovery {
+                recovery.failed = None;
+            }
+            if let Some(fields) = self.fields.filter(|_| self.de.warn_unknown_keys) {
+                if !fields.contains(&entry.key.as_str()) {
+                    self.de.warn(Warning::UnknownKey {
+                        suggestion: suggest::suggest(&entry.key, fields.iter().copied()),
+                        key: entry.key.clone(),
+                        line: entry.line,
+                    });
+                }
+            }
+            self.pending = Some(Pending::Entry(entry));
+            return Ok(Some(k));
+        }
+        if let Some(field) = self.de.recovery.as_mut().and_then(|r| r.next_missing()) {
+            self.pending = Some(Pending::Placeholder);
+            return seed
+                .deserialize(IntoDeserializer::<Error>::into_deserializer(field))
+                .map(Some);
         }
         Ok(None)
     }
@@ -184,16 +1576,78 @@ impl<'de, I: io::Read> MapAccess<'de> for PropertiesMapAccess<I> {
     where
         V: serde::de::DeserializeSeed<'de>,
     {
-        let value = self.line_value.take().unwrap();
-        seed.deserialize(field::FieldDeserializer(value))
+        let recovery = match &mut self.de.recovery {
+            None => match self.pending.take().unwrap() {
+                Pending::Entry(Entry {
+                    line, key, value, ..
+                }) if !self.de.warn_unused_keys => {
+                    return seed
+                        .deserialize(field::FieldDeserializer(value))
+                        .map_err(|e| e.at_value(&format!("{}{}", self.de.prefix, key), line))
+                }
+                Pending::Entry(Entry {
+                    line, key, value, ..
+                }) => {
+                    let mut ignored = false;
+                    let value = seed
+                        .deserialize(field::TrackingDeserializer {
+                            value,
+                            ignored: &mut ignored,
+                        })
+                        .map_err(|e| e.at_value(&format!("{}{}", self.de.prefix, key), line))?;
+                    if ignored {
+                        self.de.warn(Warning::UnusedKey { key, line });
+                    }
+                    return Ok(value);
+                }
+                Pending::Placeholder => unreachable!(),
+            },
+            Some(recovery) => recovery,
+        };
+        let result = match self.pending.take().unwrap() {
+            Pending::Entry(Entry {
+                line, key, value, ..
+            }) => {
+                if recovery.replace.contains(&line) {
+                    recovery.failed = Some(report::Failure::Value { line, key });
+                    seed.deserialize(field::PlaceholderDeserializer)
+                } else {
+                    recovery.failed = Some(report::Failure::Value {
+                        line,
+                        key: key.clone(),
+                    });
+                    let mut rejected = None;
+                    let result = seed.deserialize(field::RecoveringDeserializer {
+                        value,
+                        rejected: &mut rejected,
+                    });
+                    if let Some(error) = rejected {
+                        let error = error.at_value(&format!("{}{}", self.de.prefix, key), line);
+                        recovery.replace.insert(line);
+                        recovery.recovered(line, key, Part::Value, error);
+                    }
+                    result
+                }
+            }
+            Pending::Placeholder => {
+                recovery.failed = Some(report::Failure::Placeholder);
+                seed.deserialize(field::PlaceholderDeserializer)
+            }
+        };
+        if result.is_ok() {
+            recovery.failed = None;
+        }
+        result
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use serde::Deserialize;
 
-    use crate::de::Deserializer;
+    use crate::de::{validate, Deserializer, Warning};
 
     #[derive(Debug, Clone, PartialEq, Deserialize)]
     struct Workload {
@@ -274,4 +1728,259 @@ optional_bool_empty=
             }
         );
     }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+    enum Level {
+        Low,
+        High,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Settings {
+        name: String,
+        level: Level,
+        count: u32,
+        enabled: bool,
+        #[serde(default)]
+        retries: u8,
+        comment: Option<String>,
+    }
+
+    #[test]
+    fn test_validate() {
+        let data = "
+# a comment
+level=Medium
+count=many
+enabled=yes
+colour=blue
+retries=3
+";
+        let report = crate::validate::<Settings>(data).unwrap_err();
+        let found: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.key.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Some(3), Some("level")),
+                (Some(4), Some("count")),
+                (Some(5), Some("enabled")),
+                (Some(6), Some("colour")),
+                (None, Some("name")),
+            ]
+        );
+        assert!(matches!(
+            report.diagnostics[4].error,
+            crate::de::Error::MissingField { field: "name" }
+        ));
+
+        // Values that fail to parse, duplicates and malformed lines don't need a new run
+        let data = "name=x\ncount=many\nenabled=yes\ncount=2\nretries=\\u12\nlevel=High";
+        let mut runs = 0;
+        let report = validate::<Settings, _, _>(|| {
+            runs += 1;
+            Deserializer::from_str(data)
+        })
+        .unwrap_err();
+        assert_eq!(runs, 1);
+        let found: Vec<_> = report.diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(found, [Some(2), Some(3), Some(4), Some(5)]);
+        assert_eq!(
+            report.diagnostics[2].to_string(),
+            "line 4: `count`: Custom: \"duplicate field `count`\""
+        );
+
+        let data = "name=x\nlevel=High\ncount=1\nenabled=true";
+        let settings = crate::validate::<Settings>(data).unwrap();
+        assert_eq!(settings.level, Level::High);
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Database {
+        #[serde(rename = "database.url")]
+        url: String,
+        #[serde(rename = "database.user")]
+        user: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct StrictDatabase {
+        #[serde(rename = "database.url")]
+        url: String,
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let data = "database.url=jdbc:h2:mem\ndatbase.user=sa\ncolour=blue";
+        let err = crate::from_str::<StrictDatabase>(data).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown field `datbase.user` on line 2, did you mean `database.url`?"
+        );
+
+        let mut de = Deserializer::from_str(data);
+        de.set_warn_unknown_keys(true);
+        Database::deserialize(&mut de).unwrap();
+        assert_eq!(
+            de.warnings(),
+            [
+                Warning::UnknownKey {
+                    key: "datbase.user".to_string(),
+                    line: 2,
+                    suggestion: Some("database.user"),
+                },
+                Warning::UnknownKey {
+                    key: "colour".to_string(),
+                    line: 3,
+                    suggestion: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_keys() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Aliased {
+            #[serde(rename = "database.url", alias = "db.url")]
+            url: String,
+            #[serde(skip)]
+            user: String,
+        }
+
+        let data = "db.url=jdbc:h2:mem\nuser=sa\ncolour=blue";
+        let mut de = Deserializer::from_str(data);
+        de.set_warn_unused_keys(true);
+        Aliased::deserialize(&mut de).unwrap();
+        assert_eq!(
+            de.warnings(),
+            [
+                Warning::UnusedKey {
+                    key: "user".to_string(),
+                    line: 2,
+                },
+                Warning::UnusedKey {
+                    key: "colour".to_string(),
+                    line: 3,
+                },
+            ]
+        );
+
+        let (map, unused) = crate::from_str_with_report::<HashMap<String, String>>(data).unwrap();
+        assert_eq!(map.len(), 3);
+        assert!(unused.is_empty());
+    }
+
+    #[test]
+    fn test_lenient() {
+        let data = "a=1\nb=\\u12\nc=3\n\\uXYZW=4\n";
+        assert!(crate::from_str::<HashMap<String, u8>>(data).is_err());
+
+        let mut de = Deserializer::from_str(data);
+        de.set_lenient(true);
+        let map = HashMap::<String, u8>::deserialize(&mut de).unwrap();
+        assert_eq!(map, HashMap::from([("a".into(), 1), ("c".into(), 3)]));
+        let lines: Vec<_> = de
+            .take_warnings()
+            .into_iter()
+            .map(|w| match w {
+                Warning::MalformedLine { line, .. } => line,
+                w => panic!("unexpected warning {}", w),
+            })
+            .collect();
+        assert_eq!(lines, [2, 4]);
+    }
+
+    fn lenient_warnings(data: &[u8]) -> (HashMap<String, String>, Vec<(usize, String)>) {
+        let mut de = Deserializer::from_reader_with_encoding(data, encoding_rs::UTF_8);
+        de.set_lenient(true);
+        let map = HashMap::<String, String>::deserialize(&mut de).unwrap();
+        let warnings = de
+            .take_warnings()
+            .into_iter()
+            .map(|w| match w {
+                Warning::MalformedLine { line, message } => (line, message),
+                w => panic!("unexpected warning {}", w),
+            })
+            .collect();
+        (map, warnings)
+    }
+
+    #[test]
+    fn test_lenient_encoding() {
+        let data = b"a=1\nb=\\u12\nc=caf\xe9\nd=\\u00e9\\\n  \xff\xfe\ne=\xc3\n";
+        let (map, warnings) = lenient_warnings(data);
+        assert_eq!(map["c"], "caf\u{fffd}");
+        assert_eq!(map["d"], "\u{e9}\u{fffd}\u{fffd}");
+        assert_eq!(map["e"], "\u{fffd}");
+        let lines: Vec<_> = warnings.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2, 3, 5, 6]);
+        assert_eq!(
+            warnings[1].1,
+            "invalid UTF-8 byte sequence, replaced with U+FFFD"
+        );
+
+        // Not every replacement character comes from invalid input
+        let (map, warnings) = lenient_warnings("a=\\uFFFD\nb=\u{fffd}".as_bytes());
+        assert_eq!(map["a"], map["b"]);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_lenient_continuation() {
+        let (map, warnings) = lenient_warnings(b"a=1\nb=2\\");
+        assert_eq!(map, HashMap::from([("a".into(), "1".into())]));
+        assert_eq!(
+            warnings,
+            [(
+                2,
+                "line continuation at the end of the input, the line was skipped".to_string()
+            )]
+        );
+
+        let (map, warnings) = lenient_warnings(b"a=1\nb=2\\\n");
+        assert_eq!(map["b"], "2");
+        assert_eq!(
+            warnings,
+            [(2, "line continuation at the end of the input".to_string())]
+        );
+
+        // An escaped backslash or a comment doesn't continue the line
+        let (_, warnings) = lenient_warnings(b"a=1\\\\\n# end\\");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_prefix() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Consumer {
+            #[serde(rename = "group-id")]
+            group_id: String,
+            topic: String,
+        }
+
+        let text = "kafka.consumer.group-id=app\nkafka.producer.topic=out\nkafka.consumer.topic=${kafka.producer.topic}";
+        let mut de = Deserializer::from_str(text);
+        de.set_prefix("kafka.consumer.");
+        de.set_interpolate(true);
+        let consumer = Consumer::deserialize(de).unwrap();
+        assert_eq!(consumer.topic, "out");
+
+        let vars = [("APP_KAFKA_CONSUMER_TOPIC", "in")];
+        let mut de = Deserializer::from_str("kafka.consumer.group-id=app");
+        de.set_prefix("kafka.consumer.");
+        de.set_env_overlay(crate::env::EnvOverlay::from_vars("APP", vars));
+        let consumer = Consumer::deserialize(de).unwrap();
+        assert_eq!(consumer.topic, "in");
+    }
 }
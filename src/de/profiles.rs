@@ -0,0 +1,427 @@
+//! Spring Boot and Quarkus profiles
+
+use std::collections::{HashMap, HashSet};
+
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+use super::layered::last_wins;
+use super::{Deserializer, Entry, Error};
+
+/// The key that restricts a document to some profiles
+const ON_PROFILE: &str = "spring.config.activate.on-profile";
+
+/// Loads configuration like Spring Boot does for a list of active profiles
+///
+/// [`SpringLoader::load_dir`] reads `application.properties` and then
+/// `application-{profile}.properties` for every active profile, in order. If no
+/// profile is active, the `default` profile is. Every file is split into documents
+/// at lines consisting of `#---` or `!---`, and a document that sets
+/// `spring.config.activate.on-profile` is skipped unless the expression matches the
+/// active profiles. Expressions are comma separated lists of profile names, which
+/// can be combined with `!`, `&`, `|` and parentheses.
+///
+/// Later documents override earlier ones in the same file, and profile specific files
+/// override `application.properties`, with the last active profile taking precedence.
+/// The `spring.config.activate.on-profile` key itself is not passed on to the
+/// deserializer.
+///
+/// ```
+/// # use serde::Deserialize;
+/// use serde_java_properties::de::SpringLoader;
+///
+/// #[derive(Deserialize)]
+/// struct Server {
+///     #[serde(rename = "server.port")]
+///     port: u16,
+/// }
+///
+/// let text = "\
+/// server.port=8080
+/// #---
+/// spring.config.activate.on-profile=dev | test
+/// server.port=9090
+/// #---
+/// spring.config.activate.on-profile=prod
+/// server.port=80
+/// ";
+///
+/// let loader = SpringLoader::new(["dev"]);
+/// let server = Server::deserialize(loader.load_str(text)?)?;
+/// assert_eq!(server.port, 9090);
+/// # Ok::<(), serde_java_properties::de::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpringLoader {
+    profiles: Vec<String>,
+    name: String,
+    encoding: &'static Encoding,
+}
+
+impl SpringLoader {
+    /// Create a loader for the given active profiles
+    ///
+    /// Files are read as *ISO-8859-1* by default, like Spring Boot does.
+    pub fn new<I, S>(profiles: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut profiles: Vec<String> = profiles.into_iter().map(Into::into).collect();
+        if profiles.is_empty() {
+            profiles.push("default".to_string());
+        }
+        Self {
+            profiles,
+            name: "application".to_string(),
+            encoding: WINDOWS_1252,
+        }
+    }
+
+    /// Set the base name of the files, `application` by default
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.name = name.into();
+    }
+
+    /// Set the encoding of the files
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// The active profiles
+    pub fn profiles(&self) -> &[String] {
+        &self.profiles
+    }
+
+    /// Load a single multi-document source from a string
+    pub fn load_str(&self, text: &str) -> Result<Deserializer<io::Empty>, Error> {
+        let de = Deserializer::from_reader_with_encoding(text.as_bytes(), UTF_8);
+        let entries = self.active_entries(de.into_documents()?)?;
+        Ok(Deserializer::from_entries(last_wins(entries)))
+    }
+
+    /// Load the base file and the profile specific files from a directory
+    ///
+    /// Files that don't exist are skipped.
+    pub fn load_dir(&self, dir: impl AsRef<Path>) -> Result<Deserializer<io::Empty>, Error> {
+        let dir = dir.as_ref();
+        let mut entries = self.load_file(&dir.join(format!("{}.properties", self.name)))?;
+        for profile in &self.profiles {
+            let path = dir.join(format!("{}-{}.properties", self.name, profile));
+            entries.extend(self.load_file(&path)?);
+        }
+        Ok(Deserializer::from_entries(last_wins(entries)))
+    }
+
+    fn load_file(&self, path: &Path) -> Result<Vec<Entry>, Error> {
+        let file = match fs::File::open(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            result => result.map_err(|e| Error::in_file(path, e))?,
+        };
+        let mut de = Deserializer::from_reader_with_encoding(BufReader::new(file), self.encoding);
+        de.set_source_name(path.display().to_string());
+        de.into_documents()
+            .and_then(|documents| self.active_entries(documents))
+            .map_err(|e| Error::in_file(path, e))
+    }
+
+    /// The entries of the documents that match the active profiles
+    fn active_entries(&self, documents: Vec<Vec<Entry>>) -> Result<Vec<Entry>, Error> {
+        let mut entries = Vec::new();
+        for mut document in documents {
+            if let Some(entry) = document.iter().rev().find(|e| e.key == ON_PROFILE) {
+                if !self.matches(&entry.value, entry.line)? {
+                    continue;
+                }
+                document.retain(|e| e.key != ON_PROFILE);
+            }
+            entries.extend(document);
+        }
+        Ok(entries)
+    }
+
+    /// Evaluate a comma separated list of profile expressions
+    fn matches(&self, expressions: &str, line: usize) -> Result<bool, Error> {
+        let invalid = || Error::InvalidProfileExpression {
+            expression: expressions.to_string(),
+            line,
+        };
+        let mut any = false;
+        for expression in expressions.split(',') {
+            let tokens = tokenize(expression);
+            let mut parser = Parser {
+                tokens: &tokens,
+                pos: 0,
+                profiles: &self.profiles,
+            };
+            let matched = parser.expression().ok_or_else(invalid)?;
+            if parser.pos != tokens.len() {
+                return Err(invalid());
+            }
+            any |= matched;
+        }
+        Ok(any)
+    }
+}
+
+/// Resolve Quarkus style `%profile.key` entries for the active `profile`
+///
+/// Entries of the active profile are renamed to their plain key and replace the
+/// unprefixed entries with that key. Entries of other profiles are removed, and
+/// returned as a map from their plain key to the profiles that define it. A prefix
+/// can name several profiles, separated by commas, as in `%dev,test.key`.
+pub(crate) fn select_profile(
+    entries: &mut Vec<Entry>,
+    profile: &str,
+) -> HashMap<String, Vec<String>> {
+    let mut inactive: HashMap<String, Vec<String>> = HashMap::new();
+    let mut selected = Vec::new();
+    for mut entry in entries.drain(..) {
+        let Some((profiles, key)) = entry
+            .key
+            .strip_prefix('%')
+            .and_then(|rest| rest.split_once('.'))
+        else {
+            selected.push((entry, false));
+            continue;
+        };
+        if profiles.split(',').any(|p| p.trim() == profile) {
+            entry.key = key.to_string();
+            selected.push((entry, true));
+        } else {
+            let defined = inactive.entry(key.to_string()).or_default();
+            defined.extend(profiles.split(',').map(|p| p.trim().to_string()));
+        }
+    }
+    let overridden: HashSet<String> = selected
+        .iter()
+        .filter(|(_, profiled)| *profiled)
+        .map(|(entry, _)| entry.key.clone())
+        .collect();
+    entries.extend(
+        selected
+            .into_iter()
+            .filter(|(entry, profiled)| *profiled || !overridden.contains(&entry.key))
+            .map(|(entry, _)| entry),
+    );
+    inactive.retain(|key, _| !overridden.contains(key));
+    inactive
+}
+
+fn tokenize(expression: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in expression.char_indices() {
+        let is_operator = matches!(c, '!' | '&' | '|' | '(' | ')');
+        if is_operator || c.is_whitespace() {
+            if let Some(s) = start.take() {
+                tokens.push(&expression[s..i]);
+            }
+            if is_operator {
+                tokens.push(&expression[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&expression[s..]);
+    }
+    tokens
+}
+
+/// A recursive descent parser that evaluates a profile expression
+///
+/// As in Spring, `&` and `|` can't be mixed without parentheses.
+struct Parser<'a> {
+    tokens: &'a [&'a str],
+    pos: usize,
+    profiles: &'a [String],
+}
+
+impl Parser<'_> {
+    fn expression(&mut self) -> Option<bool> {
+        let mut value = self.operand()?;
+        let mut operator = None;
+        while let Some(&op) = self
+            .tokens
+            .get(self.pos)
+            .filter(|t| **t == "&" || **t == "|")
+        {
+            if operator.is_some_and(|previous| previous != op) {
+                return None;
+            }
+            operator = Some(op);
+            self.pos += 1;
+            let rhs = self.operand()?;
+            value = if op == "&" {
+                value && rhs
+            } else {
+                value || rhs
+            };
+        }
+        Some(value)
+    }
+
+    fn operand(&mut self) -> Option<bool> {
+        let token = *self.tokens.get(self.pos)?;
+        self.pos += 1;
+        match token {
+            "!" => self.operand().map(|value| !value),
+            "(" => {
+                let value = self.expression()?;
+                if self.tokens.get(self.pos) != Some(&")") {
+                    return None;
+                }
+                self.pos += 1;
+                Some(value)
+            }
+            "&" | "|" | ")" => None,
+            name => Some(self.profiles.iter().any(|p| p == name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    use super::SpringLoader;
+    use crate::de::{Deserializer, Error};
+
+    fn load(profiles: &[&str], text: &str) -> Result<BTreeMap<String, String>, Error> {
+        let loader = SpringLoader::new(profiles.iter().copied());
+        BTreeMap::deserialize(loader.load_str(text)?)
+    }
+
+    #[test]
+    fn test_expressions() {
+        let active = |profiles: &[&str], expression: &str| {
+            let text = format!("a=0\n#---\nspring.config.activate.on-profile={expression}\na=1");
+            load(profiles, &text).map(|map| map["a"] == "1")
+        };
+        assert!(active(&[], "default").unwrap());
+        assert!(!active(&["dev"], "default").unwrap());
+        assert!(active(&["dev"], "prod, dev").unwrap());
+        assert!(active(&["dev"], "!prod").unwrap());
+        assert!(active(&["dev", "eu"], "dev & eu").unwrap());
+        assert!(!active(&["dev"], "dev & eu").unwrap());
+        assert!(active(&["eu"], "(dev | prod) | eu").unwrap());
+        assert!(active(&["prod", "eu"], "prod & (eu | us)").unwrap());
+        assert!(!active(&["dev"], "!(dev | prod)").unwrap());
+
+        for expression in ["", "dev &", "dev & eu | us", "(dev", "dev)", "dev eu"] {
+            assert!(
+                matches!(
+                    active(&["dev"], expression),
+                    Err(Error::InvalidProfileExpression { line: 3, .. })
+                ),
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_documents() {
+        let text = "\
+a=base
+b=base
+#---
+spring.config.activate.on-profile=dev
+b=dev
+c=dev
+#---
+spring.config.activate.on-profile=prod
+a=prod
+#---
+c=last
+";
+        let map = load(&["dev"], text).unwrap();
+        let expected = [("a", "base"), ("b", "dev"), ("c", "last")];
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            expected.map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path();
+        fs::write(
+            dir.join("application.properties"),
+            "a=base\nb=base\nc=base\n#---\nspring.config.activate.on-profile=eu\nc=eu-doc",
+        )
+        .unwrap();
+        fs::write(dir.join("application-dev.properties"), "a=dev\nb=dev").unwrap();
+        fs::write(dir.join("application-eu.properties"), "b=eu").unwrap();
+        fs::write(dir.join("application-default.properties"), "a=default").unwrap();
+
+        let load_dir = |profiles: &[&str]| {
+            let loader = SpringLoader::new(profiles.iter().copied());
+            let map = BTreeMap::<String, String>::deserialize(loader.load_dir(dir).unwrap());
+            map.unwrap().into_values().collect::<Vec<_>>()
+        };
+        assert_eq!(load_dir(&["dev", "eu"]), ["dev", "eu", "eu-doc"]);
+        assert_eq!(load_dir(&["eu", "dev"]), ["dev", "dev", "eu-doc"]);
+        assert_eq!(load_dir(&[]), ["default", "base", "base"]);
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Db {
+        #[serde(rename = "db.url")]
+        url: String,
+        #[serde(rename = "db.user")]
+        user: String,
+    }
+
+    #[test]
+    fn test_quarkus_profile() {
+        let text = "\
+%dev.db.url=jdbc:h2:mem
+db.url=jdbc:postgresql://db
+db.user=app
+%prod.db.user=prod
+%dev,test.db.user=dev
+%prod.db.password=secret
+";
+        let mut de = Deserializer::from_str(text);
+        de.set_profile("dev");
+        let map = BTreeMap::<String, String>::deserialize(de).unwrap();
+        let expected = [("db.url", "jdbc:h2:mem"), ("db.user", "dev")];
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            expected.map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+
+        let mut de = Deserializer::from_str(text);
+        de.set_profile("prod");
+        let db = Db::deserialize(de).unwrap();
+        assert_eq!(
+            (db.url.as_str(), db.user.as_str()),
+            ("jdbc:postgresql://db", "prod")
+        );
+
+        let mut de = Deserializer::from_str("%dev.db.url=a\n%test.db.url=b\ndb.user=app");
+        de.set_profile("prod");
+        match Db::deserialize(de).unwrap_err() {
+            Error::MissingProfileField {
+                field,
+                profile,
+                profiles,
+            } => {
+                assert_eq!(field, "db.url");
+                assert_eq!(profile, "prod");
+                assert_eq!(profiles, ["dev", "test"]);
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Where the values of fields came from
+
+use std::fmt;
+
+use encoding_rs::UTF_8;
+use indexmap::IndexMap;
+use java_properties::PropertiesWriter;
+use serde::Serialize;
+
+use crate::ser;
+
+/// Where the value of a field came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Origin {
+    /// The field was read from a source
+    Source {
+        /// The name of the source, see [`super::Deserializer::set_source_name`]
+        ///
+        /// Environment variables are named `env:NAME`.
+        name: Option<String>,
+        /// The 1-based line number, or 0 if the value doesn't come from a line
+        line: usize,
+        /// The value as written in the source, before placeholders are resolved and
+        /// values are decrypted or transformed
+        raw: String,
+    },
+    /// The field was not set, so it got its default value
+    Defaulted,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Source {
+                name: Some(name),
+                line: 0,
+                ..
+            } => write!(f, "{}", name),
+            Self::Source {
+                name: Some(name),
+                line,
+                ..
+            } => write!(f, "{}:{}", name, line),
+            Self::Source {
+                name: None, line, ..
+            } => write!(f, "line {}", line),
+            Self::Defaulted => write!(f, "default"),
+        }
+    }
+}
+
+/// The origins of the fields of a deserialized value
+///
+/// See [`super::Deserializer::set_track_provenance`]
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use serde::Serialize;
+/// use serde_java_properties::de::{Layered, Origin};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Pool {
+///     min: usize,
+///     max: usize,
+///     #[serde(default)]
+///     idle: usize,
+/// }
+///
+/// let mut layers = Layered::new();
+/// layers.add_named_str("base.properties", "min=1\nmax=10")?;
+/// layers.add_named_str("prod.properties", "# production\nmax=3")?;
+///
+/// let mut de = layers.deserializer();
+/// de.set_track_provenance(true);
+/// let pool = Pool::deserialize(&mut de)?;
+///
+/// let provenance = de.provenance().unwrap();
+/// assert_eq!(provenance.get("idle"), Some(&Origin::Defaulted));
+/// assert_eq!(provenance.get("max").unwrap().to_string(), "prod.properties:2");
+/// assert_eq!(provenance.render(&pool).unwrap(), "\
+/// ## from: base.properties:1
+/// min=1
+/// ## from: prod.properties:2
+/// max=3
+/// ## from: default
+/// idle=0
+/// ");
+/// # Ok::<(), serde_java_properties::de::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    fields: IndexMap<String, Origin>,
+}
+
+impl Provenance {
+    /// The origin of a field, by its key
+    pub fn get(&self, key: &str) -> Option<&Origin> {
+        self.fields.get(key)
+    }
+
+    /// The keys and their origins, in the order they were read
+    ///
+    /// Defaulted fields come last.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Origin)> {
+        self.fields
+            .iter()
+            .map(|(key, origin)| (key.as_str(), origin))
+    }
+
+    pub(crate) fn record(&mut self, key: &str, origin: Origin) {
+        // A key that is read again overrides the earlier value
+        self.fields.shift_remove(key);
+        self.fields.insert(key.to_string(), origin);
+    }
+
+    /// Serialize `value`, with a `# from: ...` comment before every key whose origin
+    /// is known
+    pub fn render<T: Serialize>(&self, value: &T) -> Result<String, ser::Error> {
+        let mut out = Vec::new();
+        let mut sink = Commented {
+            provenance: self,
+            writer: PropertiesWriter::new_with_encoding(&mut out, UTF_8),
+        };
+        value.serialize(ser::Serializer::from_sink(&mut sink))?;
+        sink.writer.finish()?;
+        drop(sink);
+        Ok(String::from_utf8(out).expect("UTF-8 output"))
+    }
+}
+
+/// Writes a properties file with the origin of each key in a comment before it
+struct Commented<'a, W: std::io::Write> {
+    provenance: &'a Provenance,
+    writer: PropertiesWriter<W>,
+}
+
+impl<W: std::io::Write> ser::Sink for Commented<'_, W> {
+    fn write(&mut self, key: &str, value: &str) -> Result<(), ser::Error> {
+        if let Some(origin) = self.provenance.get(key) {
+            self.writer.write_comment(&format!("from: {}", origin))?;
+        }
+        self.writer.write(key, value)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use super::Origin;
+    use crate::de::Deserializer;
+    use crate::env::EnvOverlay;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Db {
+        url: String,
+        user: String,
+        #[serde(default)]
+        pool: usize,
+    }
+
+    #[test]
+    fn test_provenance() {
+        let text = "url=jdbc:${host}\nhost=db\nuser=sa";
+        let mut de = Deserializer::from_str(text);
+        de.set_source_name("app.properties");
+        de.set_interpolate(true);
+        de.set_env_overlay(EnvOverlay::from_vars("APP", [("APP_USER", "admin")]));
+        de.set_track_provenance(true);
+        Db::deserialize(&mut de).unwrap();
+
+        let provenance = de.provenance().unwrap();
+        assert_eq!(
+            provenance.get("url"),
+            Some(&Origin::Source {
+                name: Some("app.properties".to_string()),
+                line: 1,
+                raw: "jdbc:${host}".to_string(),
+            })
+        );
+        assert_eq!(provenance.get("user").unwrap().to_string(), "env:APP_USER");
+        assert_eq!(provenance.get("pool"), Some(&Origin::Defaulted));
+        let keys: Vec<_> = provenance.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["url", "host", "user", "pool"]);
+
+        // Tracking is opt-in, and works for maps too
+        let mut de = Deserializer::from_str(text);
+        BTreeMap::<String, String>::deserialize(&mut de).unwrap();
+        assert!(de.provenance().is_none());
+        de = Deserializer::from_str(text);
+        de.set_track_provenance(true);
+        BTreeMap::<String, String>::deserialize(&mut de).unwrap();
+        assert_eq!(
+            de.provenance().unwrap().get("user").unwrap().to_string(),
+            "line 3"
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Groups of keys that are read as separate records
+
+use std::collections::HashSet;
+use std::io;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+
+use super::{Deserializer, Entry, Error};
+
+/// An iterator over the records of an input, see [`Deserializer::into_records`]
+///
+/// Every record is yielded as soon as its keys end. A record that fails to
+/// deserialize, or has a value that fails to decrypt or transform, yields an
+/// [`Error::Record`], and the following records are still read. A key for a record
+/// that already ended yields an [`Error::SplitRecord`], or an [`Error::Record`] if its
+/// value fails to decrypt or transform. Errors that stop the input
+/// from being read, like malformed lines or exceeded [`super::Limits`], are yielded
+/// after the record that was being read.
+///
+/// Only the keys of the current record and the ids of the earlier records are kept in
+/// memory, unless an option like [`Deserializer::set_interpolate`] needs the whole
+/// input up front.
+pub struct Records<R: io::Read, T> {
+    de: Deserializer<R>,
+    prefix: String,
+    /// The record being read
+    record: Option<Record>,
+    /// The ids of the records that ended
+    ended: HashSet<String>,
+    done: bool,
+    /// An error that stopped the input from being read
+    failed: Option<Error>,
+    marker: PhantomData<fn() -> T>,
+}
+
+struct Record {
+    id: String,
+    entries: Vec<Entry>,
+    error: Option<Error>,
+}
+
+impl<R: io::Read, T: DeserializeOwned> Records<R, T> {
+    pub(super) fn new(de: Deserializer<R>, prefix: String) -> Self {
+        Self {
+            de,
+            prefix,
+            record: None,
+            ended: HashSet::new(),
+            done: false,
+            failed: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Split a key after the deserializer's own prefix into a record id and a field
+    fn split<'k>(&self, key: &'k str) -> Option<(&'k str, &'k str)> {
+        let rest = key.strip_prefix(&self.prefix)?;
+        Some(rest.split_once('.').unwrap_or((rest, "")))
+    }
+
+    /// Deserialize a record that ended
+    fn finish(&self, record: Record) -> Result<T, Error> {
+        let result = match record.error {
+            Some(e) => Err(e),
+            None => T::deserialize(Deserializer::from_entries(record.entries)),
+        };
+        result.map_err(|e| Error::Record {
+            prefix: format!("{}{}.", self.prefix, record.id),
+            source: Box::new(e),
+        })
+    }
+}
+
+impl<R: io::Read, T: DeserializeOwned> Iterator for Records<R, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let (id, result) = match self.de.next_entry() {
+                Ok(Some(mut entry)) => {
+                    let Some((id, field)) = self.split(&entry.key) else {
+                        continue;
+                    };
+                    let (id, field) = (id.to_string(), field.to_string());
+                    entry.key = field;
+                    (id, Ok(entry))
+                }
+                Ok(None) => {
+                    self.done = true;
+                    break;
+                }
+                Err(e) => {
+                    // Values that fail to decrypt or transform only spoil their record
+                    let key = match &e {
+                        Error::Transform { key, .. } => key.strip_prefix(&self.de.prefix),
+                        #[cfg(feature = "jasypt")]
+                        Error::Decryption { key, .. } => key.strip_prefix(&self.de.prefix),
+                        _ => None,
+                    };
+                    match key.and_then(|key| self.split(key)) {
+                        Some((id, _)) => (id.to_string(), Err(e)),
+                        None => {
+                            self.done = true;
+                            self.failed = Some(e);
+                            break;
+                        }
+                    }
+                }
+            };
+            if self.ended.contains(&id) {
+                return Some(Err(match result {
+                    Ok(entry) => Error::SplitRecord {
+                        key: format!("{}{}.{}", self.prefix, id, entry.key),
+                        line: entry.line,
+                    },
+                    Err(e) => Error::Record {
+                        prefix: format!("{}{}.", self.prefix, id),
+                        source: Box::new(e),
+                    },
+                }));
+            }
+            let ended = match &self.record {
+                Some(record) if record.id != id => self.record.take(),
+                _ => None,
+            };
+            let record = self.record.get_or_insert_with(|| Record {
+                id,
+                entries: Vec::new(),
+                error: None,
+            });
+            match result {
+                Ok(entry) => record.entries.push(entry),
+                Err(e) => {
+                    record.error.get_or_insert(e);
+                }
+            }
+            if let Some(ended) = ended {
+                self.ended.insert(ended.id.clone());
+                return Some(self.finish(ended));
+            }
+        }
+        match self.record.take() {
+            Some(record) => {
+                self.ended.insert(record.id.clone());
+                Some(self.finish(record))
+            }
+            None => self.failed.take().map(Err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::de::{Deserializer, Error, Transform, TransformError};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Job {
+        name: String,
+        retries: u8,
+    }
+
+    struct Reject;
+
+    impl Transform for Reject {
+        fn transform(
+            &self,
+            _: &str,
+            _: &str,
+            value: &str,
+            _: usize,
+        ) -> Result<String, TransformError> {
+            match value {
+                "bad" => Err("rejected".into()),
+                _ => Ok(value.to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_records() {
+        let text = "\
+app.name=scheduler
+job.1.name=backup
+job.1.retries=3
+job.2.name=report
+job.2.retries=many
+job.3.name=bad
+job.3.retries=1
+job.4.retries=0
+other=x
+job.4.name=cleanup
+job.1.retries=4
+job.3.retries=bad
+";
+        let mut de = Deserializer::from_str(text);
+        de.add_transform(Reject);
+        let results: Vec<_> = de.into_records::<Job>("job.").collect();
+        assert_eq!(results.len(), 6, "{results:?}");
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &Job {
+                name: "backup".to_string(),
+                retries: 3,
+            }
+        );
+        let err = results[1].as_ref().unwrap_err();
+        assert!(matches!(err, Error::Record { prefix, .. } if prefix == "job.2."));
+        assert_eq!(
+            err.to_string(),
+            "record `job.2.*`: invalid value \"many\" for `retries` on line 5: invalid digit found in string"
+        );
+        assert_eq!(
+            results[2].as_ref().unwrap_err().to_string(),
+            "record `job.3.*`: failed to transform `job.3.name` on line 6: rejected"
+        );
+        assert!(matches!(
+            &results[3],
+            Err(Error::SplitRecord { key, line: 11 }) if key == "job.1.retries"
+        ));
+        assert_eq!(
+            results[4].as_ref().unwrap_err().to_string(),
+            "record `job.3.*`: failed to transform `job.3.retries` on line 12: rejected"
+        );
+        assert_eq!(results[5].as_ref().unwrap().name, "cleanup");
+    }
+
+    #[test]
+    fn test_read_error() {
+        let mut de = Deserializer::from_str("job.1.name=a\njob.1.retries=1\njob.2.name=b\\u00");
+        de.set_prefix("job.");
+        let mut records = de.into_records::<Job>("");
+        assert_eq!(records.next().unwrap().unwrap().name, "a");
+        assert!(matches!(records.next(), Some(Err(Error::Properties(_)))));
+        assert!(records.next().is_none());
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+use std::{fmt, io};
+
+use serde::Deserialize;
+
+use super::{Deserializer, Error};
+
+/// The part of an entry that a [`Diagnostic`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part {
+    /// The key was rejected, e.g. as an unknown field
+    Key,
+    /// The value was rejected, e.g. as not a number
+    Value,
+}
+
+/// An error together with the location in the input that caused it
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// The key of the offending entry, or the name of a missing field
+    pub key: Option<String>,
+    /// The 1-based line number of the offending entry, if known
+    pub line: Option<usize>,
+    /// Whether the key or the value of the entry was rejected, if either
+    pub part: Option<Part>,
+    /// The error itself
+    pub error: Error,
+}
+
+impl Diagnostic {
+    /// A hint on how to fix the error, if there is one
+    pub fn help(&self) -> Option<String> {
+        match &self.error {
+            Error::InvalidValue { expected, .. } => Some(format!("expected {}", expected)),
+            Error::UnknownField {
+                suggestion: Some(suggestion),
+                ..
+            } => Some(format!("did you mean `{}`?", suggestion)),
+            Error::MissingField { field } => Some(format!("add a line `{}=...`", field)),
+            Error::MissingKey { key } => Some(format!("add a line `{}=...`", key)),
+            Error::InvalidChoice { choices, .. } => {
+                Some(format!("use one of `{}`", choices.join("`, `")))
+            }
+            Error::MissingProfileField { field, profile, .. } => Some(format!(
+                "add a line `{}=...` or `%{}.{}=...`",
+                field, profile, field
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Errors that know their line already name it, along with the key
+        if self.error.line().is_some() {
+            return self.error.fmt(f);
+        }
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "`{}`: ", key)?;
+        }
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for Diagnostic {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// All errors found when validating an input
+///
+/// See [`crate::validate`]
+#[derive(Debug)]
+pub struct Report {
+    /// The errors, in the order they were found
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            diagnostic.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Report {}
+
+/// Where the most recent error of a validation run came from
+pub(crate) enum Failure {
+    /// The key on this line was rejected
+    Key { line: usize, key: String },
+    /// The value on this line was rejected
+    Value { line: usize, key: String },
+    /// A placeholder for a missing field was rejected
+    Placeholder,
+}
+
+/// State carried across the runs of [`validate`]
+#[derive(Default)]
+pub(crate) struct Recovery {
+    /// Lines whose key was rejected
+    pub skip: HashSet<usize>,
+    /// Lines whose value was rejected
+    pub replace: HashSet<usize>,
+    /// Fields that are required, but missing
+    missing: Vec<&'static str>,
+    /// Number of missing fields already filled in during this run
+    filled: usize,
+    pub failed: Option<Failure>,
+    /// The errors found so far, in all runs
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Recovery {
+    pub fn next_missing(&mut self) -> Option<&'static str> {
+        let field = self.missing.get(self.filled).copied();
+        self.filled += 1;
+        field
+    }
+
+    /// Note an error that the deserializer recovered from without aborting the run
+    pub fn recovered(&mut self, line: usize, key: String, part: Part, error: Error) {
+        self.diagnostics.push(Diagnostic {
+            key: Some(key),
+            line: Some(line),
+            part: Some(part),
+            error,
+        });
+    }
+}
+
+/// Deserialize, turning each error into a [`Diagnostic`], until no new error shows up
+///
+/// Errors that the deserializer detects itself, i.e. values that fail to parse and
+/// duplicate keys, are noted in a single run, by skipping the key or standing in a
+/// placeholder for the value. Errors that a visitor raises, like unknown fields,
+/// unknown enum variants or missing fields, can't be resumed from. Then the next run
+/// starts from a fresh [`Deserializer`], with the rejected key skipped or the
+/// rejected value and missing field replaced with a placeholder.
+///
+/// An error that can't be traced to a key, value or missing field ends validation.
+pub(crate) fn validate<'de, T, R, F>(mut make: F) -> Result<T, Report>
+where
+    T: Deserialize<'de>,
+    R: io::Read,
+    F: FnMut() -> Deserializer<R>,
+{
+    let mut recovery = Recovery::default();
+    loop {
+        let mut de = make();
+        recovery.filled = 0;
+        de.recovery = Some(recovery);
+        let result = T::deserialize(&mut de);
+        recovery = de.recovery.take().unwrap();
+        let error = match result {
+            Ok(value) if recovery.diagnostics.is_empty() => return Ok(value),
+            Ok(_) => break,
+            Err(error) => error,
+        };
+        let diagnostic = match recovery.failed.take() {
+            Some(Failure::Key { line, key }) if recovery.skip.insert(line) => Diagnostic {
+                key: Some(key),
+                line: Some(line),
+                part: Some(Part::Key),
+                error,
+            },
+            Some(Failure::Value { line, key }) if recovery.replace.insert(line) => Diagnostic {
+                key: Some(key),
+                line: Some(line),
+                part: Some(Part::Value),
+                error,
+            },
+            // A placeholder was rejected, which says nothing about the input
+            Some(_) => break,
+            None => match error {
+                Error::MissingField { field } | Error::MissingProfileField { field, .. }
+                    if !recovery.missing.contains(&field) =>
+                {
+                    recovery.missing.push(field);
+                    Diagnostic {
+                        key: Some(field.to_string()),
+                        line: None,
+                        part: None,
+                        error,
+                    }
+                }
+                error => {
+                    recovery.diagnostics.push(Diagnostic {
+                        key: None,
+                        line: error.line(),
+                        part: None,
+                        error,
+                    });
+                    break;
+                }
+            },
+        };
+        recovery.diagnostics.push(diagnostic);
+    }
+    Err(Report {
+        diagnostics: recovery.diagnostics,
+    })
+}
@@ -0,0 +1,278 @@
+//! Rendering of [`Report`]s and [`Error`]s with [`miette`]
+
+use std::fmt;
+
+use miette::{LabeledSpan, NamedSource, SourceSpan};
+
+use super::{Diagnostic, Error, Part, Report};
+
+/// A [`Report`] together with the input it was created from
+///
+/// This implements [`miette::Diagnostic`], so that each error can be shown
+/// with a snippet of the offending line, a label under the key or value
+/// and a hint about the expected values.
+pub struct SourceReport {
+    source: NamedSource<String>,
+    diagnostics: Vec<SourceDiagnostic>,
+}
+
+impl Report {
+    /// Attach the input that this report was created from
+    ///
+    /// The `name` is usually the path of the file.
+    pub fn with_source(self, name: impl AsRef<str>, source: impl Into<String>) -> SourceReport {
+        let source = source.into();
+        let diagnostics = self
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                let span =
+                    (diagnostic.line).and_then(|line| find_span(&source, line, diagnostic.part));
+                SourceDiagnostic { diagnostic, span }
+            })
+            .collect();
+        SourceReport {
+            source: NamedSource::new(name, source).with_language("properties"),
+            diagnostics,
+        }
+    }
+}
+
+impl Error {
+    /// Attach the input that this error was raised for
+    ///
+    /// Errors that know their line, like values that fail to parse, are shown with a
+    /// snippet of that line. The `name` is usually the path of the file.
+    pub fn with_source(self, name: impl AsRef<str>, source: impl Into<String>) -> SourceReport {
+        let diagnostic = Diagnostic {
+            key: None,
+            line: self.line(),
+            part: self.part(),
+            error: self,
+        };
+        let report = Report {
+            diagnostics: vec![diagnostic],
+        };
+        report.with_source(name, source)
+    }
+
+    /// The part of its entry that the error is about
+    fn part(&self) -> Option<Part> {
+        match self {
+            Self::UnknownField { .. }
+            | Self::DeprecatedKeyConflict { .. }
+            | Self::SplitRecord { .. } => Some(Part::Key),
+            Self::InvalidValue { .. }
+            | Self::Transform { .. }
+            | Self::UnresolvedPlaceholder { .. }
+            | Self::ResolverFailed { .. }
+            | Self::PlaceholderCycle { .. }
+            | Self::PlaceholderTooDeep { .. } => Some(Part::Value),
+            #[cfg(feature = "jasypt")]
+            Self::Decryption { .. } => Some(Part::Value),
+            Self::Record { source, .. } => source.part(),
+            _ => None,
+        }
+    }
+}
+
+impl SourceReport {
+    /// The report without the source
+    pub fn into_report(self) -> Report {
+        Report {
+            diagnostics: self.diagnostics.into_iter().map(|d| d.diagnostic).collect(),
+        }
+    }
+}
+
+impl fmt::Debug for SourceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceReport")
+            .field("name", &self.source.name())
+            .field("diagnostics", &self.diagnostics)
+            .finish()
+    }
+}
+
+impl fmt::Display for SourceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.diagnostics.len() {
+            1 => write!(f, "1 error in {}", self.source.name()),
+            n => write!(f, "{} errors in {}", n, self.source.name()),
+        }
+    }
+}
+
+impl std::error::Error for SourceReport {}
+
+impl miette::Diagnostic for SourceReport {
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.source)
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn miette::Diagnostic> + 'a>> {
+        Some(Box::new(
+            self.diagnostics
+                .iter()
+                .map(|d| d as &dyn miette::Diagnostic),
+        ))
+    }
+}
+
+impl miette::Diagnostic for Diagnostic {
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.help().map(|h| Box::new(h) as Box<dyn fmt::Display>)
+    }
+}
+
+#[derive(Debug)]
+struct SourceDiagnostic {
+    diagnostic: Diagnostic,
+    span: Option<SourceSpan>,
+}
+
+impl fmt::Display for SourceDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.diagnostic.fmt(f)
+    }
+}
+
+impl std::error::Error for SourceDiagnostic {}
+
+impl miette::Diagnostic for SourceDiagnostic {
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.diagnostic
+            .help()
+            .map(|h| Box::new(h) as Box<dyn fmt::Display>)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let span = self.span?;
+        let label = self.diagnostic.error.to_string();
+        Some(Box::new(std::iter::once(
+            LabeledSpan::new_primary_with_span(Some(label), span),
+        )))
+    }
+}
+
+/// Find the key or value on the (1-based) physical line `line`, or the whole line
+///
+/// For values that continue on the following lines, only the part on the
+/// first line is returned.
+fn find_span(source: &str, line: usize, part: Option<Part>) -> Option<SourceSpan> {
+    let (start, text) = nth_line(source, line)?;
+    let Some(part) = part else {
+        return Some((start, text.len()).into());
+    };
+
+    let is_blank = |c: char| matches!(c, ' ' | '\t' | '\x0c');
+    let key_start = text.len() - text.trim_start_matches(is_blank).len();
+    let mut key_end = text.len();
+    let mut escaped = false;
+    for (i, c) in text[key_start..].char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ':' || c == '=' || is_blank(c) {
+            key_end = key_start + i;
+            break;
+        }
+    }
+    if part == Part::Key {
+        return Some((start + key_start, key_end - key_start).into());
+    }
+
+    let rest = text[key_end..].trim_start_matches(is_blank);
+    let rest = rest
+        .strip_prefix([':', '='])
+        .unwrap_or(rest)
+        .trim_start_matches(is_blank);
+    let value_start = text.len() - rest.len();
+    let trailing = rest.len() - rest.trim_end_matches('\\').len();
+    let value_end = text.len() - trailing % 2;
+    Some((start + value_start, value_end - value_start).into())
+}
+
+/// Get the byte offset and text of the (1-based) physical line `line`
+fn nth_line(source: &str, line: usize) -> Option<(usize, &str)> {
+    let mut start = 0;
+    let mut current = 1;
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\r' || c == '\n' {
+            if current == line {
+                return Some((start, &source[start..i]));
+            }
+            if c == '\r' && matches!(chars.peek(), Some((_, '\n'))) {
+                chars.next();
+            }
+            start = chars.peek().map_or(source.len(), |(i, _)| *i);
+            current += 1;
+        }
+    }
+    (current == line).then(|| (start, &source[start..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use miette::Diagnostic as _;
+    use serde::Deserialize;
+
+    use super::{find_span, nth_line};
+    use crate::de::Part::{Key, Value};
+
+    #[test]
+    fn test_find_span() {
+        let source = "# comment\r\nkey = value\rport:\\\n  8080\nother\\ key  x";
+        assert_eq!(nth_line(source, 2), Some((11, "key = value")));
+        assert_eq!(find_span(source, 2, Some(Key)), Some((11, 3).into()));
+        assert_eq!(find_span(source, 2, Some(Value)), Some((17, 5).into()));
+        assert_eq!(find_span(source, 2, None), Some((11, 11).into()));
+        assert_eq!(find_span(source, 3, Some(Value)), Some((28, 0).into()));
+        assert_eq!(find_span(source, 5, Some(Key)), Some((37, 10).into()));
+        assert_eq!(find_span(source, 5, Some(Value)), Some((49, 1).into()));
+        assert_eq!(find_span(source, 6, Some(Value)), None);
+    }
+
+    #[derive(Debug, Deserialize)]
+    enum Mode {
+        Fast,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Server {
+        host: String,
+        port: u16,
+        mode: Option<Mode>,
+    }
+
+    #[test]
+    fn test_source_report() {
+        let source = "host=localhost\nport=http\nmode=slow\n";
+        let report = crate::validate::<Server>(source)
+            .unwrap_err()
+            .with_source("server.properties", source);
+        assert_eq!(report.to_string(), "2 errors in server.properties");
+
+        let related: Vec<_> = report.related().unwrap().collect();
+        let label = related[0].labels().unwrap().next().unwrap();
+        assert_eq!((label.offset(), label.len()), (20, 4));
+        assert_eq!(
+            related[0].help().unwrap().to_string(),
+            "expected an integer between 0 and 65535"
+        );
+        // Errors raised by a visitor point at the value too
+        let label = related[1].labels().unwrap().next().unwrap();
+        assert_eq!((label.offset(), label.len()), (30, 4));
+
+        let report = crate::from_str::<Server>(source)
+            .unwrap_err()
+            .with_source("server.properties", source);
+        assert_eq!(report.to_string(), "1 error in server.properties");
+        let related: Vec<_> = report.related().unwrap().collect();
+        let label = related[0].labels().unwrap().next().unwrap();
+        assert_eq!((label.offset(), label.len()), (20, 4));
+    }
+}
@@ -0,0 +1,63 @@
+//! Suggestions for misspelled keys
+
+/// Find the field name closest to `key`, if any is close enough to be a typo
+pub(crate) fn suggest<'a, I>(key: &str, fields: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let limit = (key.chars().count() / 3).max(1);
+    fields
+        .into_iter()
+        .map(|field| (edit_distance(key, field), field))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, field)| field)
+}
+
+/// The edit distance between two strings, counted in [`char`]s
+///
+/// Swapping two adjacent characters counts as a single edit, since that is a
+/// common typo (optimal string alignment distance).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(d[i - 2][j - 2] + 1);
+            }
+            d[i][j] = best;
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edit_distance, suggest};
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(edit_distance("datbase.url", "database.url"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("prot", "port"), 1);
+
+        let fields = ["database.url", "database.user", "port"];
+        assert_eq!(suggest("datbase.url", fields), Some("database.url"));
+        assert_eq!(suggest("database.usr", fields), Some("database.user"));
+        assert_eq!(suggest("prot", fields), Some("port"));
+        assert_eq!(suggest("colour", fields), None);
+    }
+}
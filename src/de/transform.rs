@@ -0,0 +1,141 @@
+//! Rewriting of values before they are parsed
+
+/// The error type of [`Transform`]s
+pub type TransformError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Rewrites values before they are parsed
+///
+/// A transform sees both the value as read and the text that would otherwise be
+/// parsed. See [`super::Deserializer::add_transform`]
+pub trait Transform {
+    /// Get the new value for the entry `key` on `line`
+    ///
+    /// `raw` is the value as read, after escapes are processed, so a transform can tell
+    /// `${...}` placeholders and `ENC(...)` values from literal text. `value` is the
+    /// current value: with placeholders resolved, encrypted values decrypted, and
+    /// rewritten by the transforms that ran before.
+    fn transform(
+        &self,
+        key: &str,
+        raw: &str,
+        value: &str,
+        line: usize,
+    ) -> Result<String, TransformError>;
+}
+
+impl<F> Transform for F
+where
+    F: Fn(&str, &str, &str, usize) -> Result<String, TransformError>,
+{
+    fn transform(
+        &self,
+        key: &str,
+        raw: &str,
+        value: &str,
+        line: usize,
+    ) -> Result<String, TransformError> {
+        self(key, raw, value, line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use super::TransformError;
+    use crate::de::{Deserializer, Error};
+
+    #[test]
+    fn test_transforms() {
+        let text = "a=\"quoted\"\nb=N/A\nc=~/data\nd=\"N/A\"\ne=bad";
+        let mut de = Deserializer::from_str(text);
+        de.add_transform(
+            |_: &str, _: &str, value: &str, _| -> Result<String, TransformError> {
+                let unquoted = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'));
+                Ok(unquoted.unwrap_or(value).to_string())
+            },
+        );
+        de.add_transform(
+            |key: &str, _: &str, value: &str, line| -> Result<String, TransformError> {
+                match value {
+                    "N/A" => Ok(String::new()),
+                    "bad" => Err(format!("bad value for {} on line {}", key, line).into()),
+                    _ => Ok(value.replace('~', "/home/app")),
+                }
+            },
+        );
+        let err = BTreeMap::<String, String>::deserialize(&mut de).unwrap_err();
+        assert!(matches!(&err, Error::Transform { line: 5, .. }), "{err}");
+
+        let mut de = Deserializer::from_str(&text[..text.len() - 6]);
+        de.add_transform(
+            |_: &str, _: &str, value: &str, _| -> Result<String, TransformError> {
+                Ok(value.trim_matches('"').replace("N/A", ""))
+            },
+        );
+        let map = BTreeMap::<String, Option<String>>::deserialize(de).unwrap();
+        assert_eq!(map["a"].as_deref(), Some("quoted"));
+        assert_eq!(map["b"], None);
+        assert_eq!(map["d"], None);
+
+        // Placeholders are resolved first
+        let mut de = Deserializer::from_str("a=x\nb=${a}");
+        de.set_interpolate(true);
+        de.add_transform(
+            |_: &str, _: &str, value: &str, _| -> Result<String, TransformError> {
+                Ok(value.to_uppercase())
+            },
+        );
+        let map = BTreeMap::<String, String>::deserialize(de).unwrap();
+        assert_eq!(map["b"], "X");
+
+        // The raw value tells placeholders from literal text
+        let mut de = Deserializer::from_str("a=x\nb=${a}\nc=$${a}");
+        de.set_interpolate(true);
+        de.add_transform(
+            |key: &str, raw: &str, value: &str, _| -> Result<String, TransformError> {
+                match key {
+                    "b" => assert_eq!((raw, value), ("${a}", "x")),
+                    "c" => assert_eq!((raw, value), ("$${a}", "${a}")),
+                    _ => assert_eq!(raw, value),
+                }
+                Ok(format!("{}<-{}", value, raw))
+            },
+        );
+        de.add_transform(
+            |_: &str, raw: &str, value: &str, _| -> Result<String, TransformError> {
+                // Later transforms see the same raw value
+                assert!(value.ends_with(raw));
+                Ok(value.to_string())
+            },
+        );
+        let map = BTreeMap::<String, String>::deserialize(de).unwrap();
+        assert_eq!(map["b"], "x<-${a}");
+    }
+
+    #[cfg(feature = "jasypt")]
+    #[test]
+    fn test_transform_encrypted() {
+        use crate::jasypt::{Algorithm, Jasypt};
+
+        let jasypt = Jasypt::new(Algorithm::PbeWithMd5AndDes, "secret");
+        let encrypted = jasypt.encrypt("hunter2").unwrap();
+        let text = format!("password={}\nuser=app", encrypted);
+        let mut de = Deserializer::from_str(&text);
+        de.set_jasypt(jasypt);
+        de.add_transform(
+            |_: &str, raw: &str, value: &str, _| -> Result<String, TransformError> {
+                // Only mark values that were encrypted in the input
+                Ok(match raw.starts_with("ENC(") {
+                    true => format!("secret:{}", value),
+                    false => value.to_string(),
+                })
+            },
+        );
+        let map = BTreeMap::<String, String>::deserialize(de).unwrap();
+        assert_eq!(map["password"], "secret:hunter2");
+        assert_eq!(map["user"], "app");
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+
+/// A problem with the input that did not stop deserialization
+///
+/// See [`super::Deserializer::warnings`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// A key that doesn't match any field of the struct
+    UnknownKey {
+        /// The key as found in the input
+        key: String,
+        /// The 1-based line number of the key
+        line: usize,
+        /// A field with a similar name
+        suggestion: Option<&'static str>,
+    },
+    /// A key whose value was not used by any field
+    ///
+    /// See [`super::Deserializer::set_warn_unused_keys`]
+    UnusedKey {
+        /// The key as found in the input
+        key: String,
+        /// The 1-based line number of the key
+        line: usize,
+    },
+    /// A key that was renamed, but is still accepted
+    ///
+    /// See [`super::Deserializer::add_deprecated_key`]
+    DeprecatedKey {
+        /// The deprecated key
+        key: String,
+        /// The key that replaces it
+        replacement: String,
+        /// Why the key was renamed, or how to migrate
+        message: String,
+        /// The 1-based line number of the key
+        line: usize,
+    },
+    /// Malformed input that was skipped or repaired
+    ///
+    /// See [`super::Deserializer::set_lenient`]
+    MalformedLine {
+        /// The 1-based line number
+        line: usize,
+        /// What was wrong, and how it was handled
+        message: String,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownKey {
+                key,
+                line,
+                suggestion,
+            } => {
+                write!(f, "line {}: unknown key `{}`", line, key)?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean `{}`?", suggestion)?;
+                }
+                Ok(())
+            }
+            Self::UnusedKey { key, line } => {
+                write!(f, "line {}: unused key `{}`", line, key)
+            }
+            Self::DeprecatedKey {
+                key,
+                replacement,
+                message,
+                line,
+            } => {
+                write!(
+                    f,
+                    "line {}: `{}` is deprecated, use `{}` instead",
+                    line, key, replacement
+                )?;
+                if !message.is_empty() {
+                    write!(f, ": {}", message)?;
+                }
+                Ok(())
+            }
+            Self::MalformedLine { line, message } => {
+                write!(f, "line {}: malformed input: {}", line, message)
+            }
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! Environment variable overrides
+//!
+//! Keys are mapped to environment variable names like in Spring Boot and MicroProfile
+//! Config: the key is upper-cased, every character other than an ASCII letter or digit,
+//! e.g. `.` or `-`, is replaced with `_`, and the result is appended to a prefix. With
+//! the prefix `MYAPP`, the key `db.pool.max` maps to `MYAPP_DB_POOL_MAX`.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::de::Entry;
+use crate::ser;
+
+/// Environment variables that override the values of a properties source
+///
+/// Variable names are matched case-insensitively. See
+/// [`Deserializer::set_env_overlay`](crate::Deserializer::set_env_overlay).
+#[derive(Debug, Clone)]
+pub struct EnvOverlay {
+    prefix: String,
+    /// Upper-cased names and values, or `None` to read the process environment
+    vars: Option<HashMap<String, String>>,
+}
+
+impl EnvOverlay {
+    /// Override values with the environment variables of the process
+    ///
+    /// The environment is read when deserializing starts. Variables that are not
+    /// valid unicode are ignored.
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            vars: None,
+        }
+    }
+
+    /// Override values with the given variables instead of the process environment
+    pub fn from_vars<I, K, V>(prefix: impl Into<String>, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Into<String>,
+    {
+        let vars = vars
+            .into_iter()
+            .map(|(name, value)| (name.as_ref().to_ascii_uppercase(), value.into()))
+            .collect();
+        Self {
+            prefix: prefix.into(),
+            vars: Some(vars),
+        }
+    }
+
+    /// The name of the environment variable for `key`
+    ///
+    /// ```
+    /// use serde_java_properties::env::EnvOverlay;
+    ///
+    /// let overlay = EnvOverlay::new("MYAPP");
+    /// assert_eq!(overlay.var_name("db.pool.max"), "MYAPP_DB_POOL_MAX");
+    /// assert_eq!(overlay.var_name("log-level"), "MYAPP_LOG_LEVEL");
+    /// ```
+    pub fn var_name(&self, key: &str) -> String {
+        var_name(&self.prefix, key)
+    }
+
+    /// Replace the values of `entries` whose variable is set
+    ///
+    /// `fields` that are not in `entries` are appended if their variable is set, as
+    /// there is no way to tell the key of an arbitrary variable from its name.
+    pub(crate) fn apply(&self, entries: &mut Vec<Entry>, fields: &[String]) {
+        let env;
+        let vars = match &self.vars {
+            Some(vars) => vars,
+            None => {
+                env = std::env::vars_os()
+                    .filter_map(|(name, value)| {
+                        Some((name.into_string().ok()?, value.into_string().ok()?))
+                    })
+                    .map(|(name, value)| (name.to_ascii_uppercase(), value))
+                    .collect();
+                &env
+            }
+        };
+        for entry in entries.iter_mut() {
+            let name = self.var_name(&entry.key);
+            if let Some(value) = vars.get(&name) {
+                entry.value.clone_from(value);
+                // The value no longer comes from the line of the key
+                entry.line = 0;
+                entry.source = Some(format!("env:{}", name).into());
+                entry.raw = Some(value.clone());
+            }
+        }
+        for field in fields {
+            if entries.iter().any(|entry| entry.key == *field) {
+                continue;
+            }
+            let name = self.var_name(field);
+            if let Some(value) = vars.get(&name) {
+                entries.push(Entry {
+                    line: 0,
+                    key: field.to_string(),
+                    value: value.clone(),
+                    source: Some(format!("env:{}", name).into()),
+                    raw: Some(value.clone()),
+                });
+            }
+        }
+    }
+}
+
+fn var_name(prefix: &str, key: &str) -> String {
+    let mut name = prefix.to_ascii_uppercase();
+    if !name.is_empty() && !name.ends_with('_') {
+        name.push('_');
+    }
+    name.extend(key.chars().map(|c| match c {
+        'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+        _ => '_',
+    }));
+    name
+}
+
+/// A [`ser::Sink`] that collects environment variables, named as in
+/// [`EnvOverlay::var_name`]
+///
+/// Serialize into it with [`Serializer::from_sink`](crate::Serializer::from_sink) to
+/// transform or encrypt values on the way, or use [`to_env_vars`] when neither is needed.
+///
+/// ```
+/// # use serde::Serialize;
+/// use serde_java_properties::env::EnvVars;
+/// use serde_java_properties::ser::TransformError;
+/// use serde_java_properties::Serializer;
+///
+/// #[derive(Serialize)]
+/// struct Log {
+///     level: String,
+/// }
+///
+/// let mut vars = EnvVars::new("MYAPP");
+/// let mut ser = Serializer::from_sink(&mut vars);
+/// ser.add_transform(|_: &str, value: &str| -> Result<String, TransformError> {
+///     Ok(value.to_uppercase())
+/// });
+/// Log { level: "debug".to_string() }.serialize(ser)?;
+///
+/// assert_eq!(vars.into_vars(), [("MYAPP_LEVEL".to_string(), "DEBUG".to_string())]);
+/// # Ok::<(), serde_java_properties::ser::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EnvVars {
+    prefix: String,
+    vars: Vec<(String, String)>,
+}
+
+impl EnvVars {
+    /// Collect variables whose names start with `prefix`
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            vars: Vec::new(),
+        }
+    }
+
+    /// The names and values of the variables, in the order they were written
+    pub fn into_vars(self) -> Vec<(String, String)> {
+        self.vars
+    }
+}
+
+impl ser::Sink for EnvVars {
+    fn write(&mut self, key: &str, value: &str) -> Result<(), ser::Error> {
+        self.vars
+            .push((var_name(&self.prefix, key), value.to_string()));
+        Ok(())
+    }
+}
+
+/// Turn a value into environment variables, named as in [`EnvOverlay::var_name`]
+///
+/// This accepts the same structures as [`crate::Serializer`], and returns the
+/// variables in the order of the serialized fields. See [`EnvVars`] to transform or
+/// encrypt the values.
+///
+/// ```
+/// # use serde::Serialize;
+/// #
+/// #[derive(Serialize)]
+/// struct Pool {
+///     #[serde(rename = "db.pool.max")]
+///     max: usize,
+///     #[serde(rename = "db.pool.idle-timeout")]
+///     idle_timeout: u64,
+/// }
+///
+/// let vars = serde_java_properties::env::to_env_vars(&Pool { max: 10, idle_timeout: 60 }, "MYAPP")?;
+/// assert_eq!(vars, [
+///     ("MYAPP_DB_POOL_MAX".to_string(), "10".to_string()),
+///     ("MYAPP_DB_POOL_IDLE_TIMEOUT".to_string(), "60".to_string()),
+/// ]);
+/// # Ok::<(), serde_java_properties::ser::Error>(())
+/// ```
+pub fn to_env_vars<T: Serialize>(
+    value: &T,
+    prefix: &str,
+) -> Result<Vec<(String, String)>, ser::Error> {
+    let mut vars = EnvVars::new(prefix);
+    value.serialize(ser::Serializer::from_sink(&mut vars))?;
+    Ok(vars.into_vars())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{var_name, EnvOverlay, EnvVars};
+    use crate::ser::TransformError;
+    use crate::{Deserializer, Serializer};
+
+    #[test]
+    fn test_var_name() {
+        assert_eq!(var_name("", "a.b"), "A_B");
+        assert_eq!(var_name("app_", "a.b"), "APP_A_B");
+        assert_eq!(var_name("app", "server.port-2"), "APP_SERVER_PORT_2");
+        assert_eq!(var_name("APP", "läuft"), "APP_L_UFT");
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Pool {
+        #[serde(rename = "db.pool.min")]
+        min: usize,
+        #[serde(rename = "db.pool.max")]
+        max: usize,
+        #[serde(rename = "db.url")]
+        url: String,
+    }
+
+    #[test]
+    fn test_overlay() {
+        let vars = [
+            ("myapp_db_pool_max", "20"),
+            ("MYAPP_DB_URL", "postgres://db"),
+            ("OTHER_DB_POOL_MIN", "5"),
+        ];
+        let mut de = Deserializer::from_str("db.pool.min=1\ndb.pool.max=10");
+        de.set_env_overlay(EnvOverlay::from_vars("MYAPP", vars));
+        let pool = Pool::deserialize(de).unwrap();
+        assert_eq!(
+            pool,
+            Pool {
+                min: 1,
+                max: 20,
+                url: "postgres://db".to_string(),
+            }
+        );
+
+        // Without fields, only keys in the input can be overridden
+        let mut de = Deserializer::from_str("db.pool.min=1\ndb.pool.max=10");
+        de.set_env_overlay(EnvOverlay::from_vars("MYAPP", vars));
+        let map = BTreeMap::<String, String>::deserialize(de).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["db.pool.max"], "20");
+    }
+
+    #[derive(Serialize)]
+    struct Db {
+        user: String,
+        password: String,
+    }
+
+    fn db() -> Db {
+        Db {
+            user: "app".to_string(),
+            password: "hunter2".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let mut vars = EnvVars::new("MYAPP");
+        let mut ser = Serializer::from_sink(&mut vars);
+        ser.add_transform(|key: &str, value: &str| -> Result<String, TransformError> {
+            Ok(format!("{}:{}", key, value))
+        });
+        db().serialize(ser.with_prefix("db.")).unwrap();
+
+        assert_eq!(
+            vars.into_vars(),
+            [
+                ("MYAPP_DB_USER".to_string(), "db.user:app".to_string()),
+                (
+                    "MYAPP_DB_PASSWORD".to_string(),
+                    "db.password:hunter2".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[cfg(feature = "jasypt")]
+    #[test]
+    fn test_export_encrypted() {
+        use crate::jasypt::{Algorithm, Jasypt};
+
+        let mut vars = EnvVars::new("MYAPP");
+        let mut ser = Serializer::from_sink(&mut vars);
+        ser.encrypt_fields(
+            Jasypt::new(Algorithm::PbeWithMd5AndDes, "secret"),
+            ["db.password"],
+        );
+        db().serialize(ser.with_prefix("db.")).unwrap();
+        let vars = vars.into_vars();
+
+        assert_eq!(vars[0], ("MYAPP_DB_USER".to_string(), "app".to_string()));
+        assert_eq!(vars[1].0, "MYAPP_DB_PASSWORD");
+        let jasypt = Jasypt::new(Algorithm::PbeWithMd5AndDes, "secret");
+        assert_eq!(
+            jasypt.decrypt_value(&vars[1].1).unwrap().unwrap(),
+            "hunter2"
+        );
+    }
+}
@@ -0,0 +1,442 @@
+//! Jasypt compatible encryption of values
+//!
+//! [Jasypt](http://www.jasypt.org/) stores encrypted values as `ENC(...)`, where `...`
+//! is the base64 encoding of the random salt, the random IV (for AES based algorithms)
+//! and the ciphertext. The key is derived from a password with the password based
+//! encryption (PBE) scheme of the algorithm.
+//!
+//! This module is only available with the `jasypt` feature.
+
+use std::{env, fmt};
+
+use aes::{Aes128, Aes256};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use des::Des;
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use crate::de::ResolverError;
+
+/// The number of key derivation iterations that Jasypt uses by default
+pub const DEFAULT_ITERATIONS: u32 = 1000;
+
+/// A password based encryption algorithm, named as in Java
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// `PBEWithMD5AndDES`, the default of Jasypt
+    PbeWithMd5AndDes,
+    /// `PBEWithHMACSHA1AndAES_128`
+    PbeWithHmacSha1AndAes128,
+    /// `PBEWithHMACSHA1AndAES_256`
+    PbeWithHmacSha1AndAes256,
+    /// `PBEWithHMACSHA256AndAES_128`
+    PbeWithHmacSha256AndAes128,
+    /// `PBEWithHMACSHA256AndAES_256`
+    PbeWithHmacSha256AndAes256,
+    /// `PBEWithHMACSHA512AndAES_128`
+    PbeWithHmacSha512AndAes128,
+    /// `PBEWithHMACSHA512AndAES_256`, the default of jasypt-spring-boot 3
+    PbeWithHmacSha512AndAes256,
+}
+
+impl Algorithm {
+    const ALL: [Algorithm; 7] = [
+        Self::PbeWithMd5AndDes,
+        Self::PbeWithHmacSha1AndAes128,
+        Self::PbeWithHmacSha1AndAes256,
+        Self::PbeWithHmacSha256AndAes128,
+        Self::PbeWithHmacSha256AndAes256,
+        Self::PbeWithHmacSha512AndAes128,
+        Self::PbeWithHmacSha512AndAes256,
+    ];
+
+    /// The Java name of the algorithm
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PbeWithMd5AndDes => "PBEWithMD5AndDES",
+            Self::PbeWithHmacSha1AndAes128 => "PBEWithHMACSHA1AndAES_128",
+            Self::PbeWithHmacSha1AndAes256 => "PBEWithHMACSHA1AndAES_256",
+            Self::PbeWithHmacSha256AndAes128 => "PBEWithHMACSHA256AndAES_128",
+            Self::PbeWithHmacSha256AndAes256 => "PBEWithHMACSHA256AndAES_256",
+            Self::PbeWithHmacSha512AndAes128 => "PBEWithHMACSHA512AndAES_128",
+            Self::PbeWithHmacSha512AndAes256 => "PBEWithHMACSHA512AndAES_256",
+        }
+    }
+
+    /// Look up an algorithm by its Java name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The size of the salt and the IV, which is the block size of the cipher
+    fn block_size(self) -> usize {
+        match self {
+            Self::PbeWithMd5AndDes => 8,
+            _ => 16,
+        }
+    }
+
+    fn has_iv(self) -> bool {
+        self != Self::PbeWithMd5AndDes
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Where the encryption password comes from
+///
+/// Closures returning `Result<String, ResolverError>` can be used as a source, so
+/// that the password is only fetched when it is needed.
+pub trait PasswordSource {
+    /// Get the password
+    fn password(&self) -> Result<String, ResolverError>;
+}
+
+impl PasswordSource for String {
+    fn password(&self) -> Result<String, ResolverError> {
+        Ok(self.clone())
+    }
+}
+
+impl PasswordSource for &str {
+    fn password(&self) -> Result<String, ResolverError> {
+        Ok(self.to_string())
+    }
+}
+
+impl<F> PasswordSource for F
+where
+    F: Fn() -> Result<String, ResolverError>,
+{
+    fn password(&self) -> Result<String, ResolverError> {
+        self()
+    }
+}
+
+/// Reads the password from an environment variable
+///
+/// jasypt-spring-boot uses `JASYPT_ENCRYPTOR_PASSWORD`.
+#[derive(Debug, Clone)]
+pub struct EnvPassword(pub String);
+
+impl PasswordSource for EnvPassword {
+    fn password(&self) -> Result<String, ResolverError> {
+        env::var(&self.0).map_err(|e| format!("{}: {}", self.0, e).into())
+    }
+}
+
+/// An error while encrypting or decrypting a value
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The password could not be read from its source
+    Password(ResolverError),
+    /// The encrypted value is not valid base64
+    Base64(base64::DecodeError),
+    /// The encrypted value is too short or not a multiple of the block size
+    Malformed,
+    /// The decrypted value has an invalid padding, usually because the password is wrong
+    Decrypt,
+    /// The decrypted value is not valid UTF-8
+    Utf8(std::string::FromUtf8Error),
+    /// No random salt could be generated
+    Random(getrandom::Error),
+    /// The password has characters other than printable ASCII, which Java doesn't accept
+    /// for the algorithm
+    ///
+    /// Java only derives keys from printable ASCII passwords for `PBEWithMD5AndDES`, so
+    /// a ciphertext for another password couldn't be decrypted there.
+    NonAsciiPassword(Algorithm),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Password(e) => write!(f, "failed to get the encryption password: {}", e),
+            Self::Base64(e) => write!(f, "encrypted value is not valid base64: {}", e),
+            Self::Malformed => write!(f, "encrypted value is malformed"),
+            Self::Decrypt => write!(f, "decryption failed, the password may be wrong"),
+            Self::Utf8(e) => write!(f, "decrypted value is not valid UTF-8: {}", e),
+            Self::Random(e) => write!(f, "failed to generate a salt: {}", e),
+            Self::NonAsciiPassword(algorithm) => {
+                write!(f, "{} only supports printable ASCII passwords", algorithm)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Password(e) => Some(e.as_ref()),
+            Self::Base64(e) => Some(e),
+            Self::Utf8(e) => Some(e),
+            Self::Random(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A Jasypt compatible string encryptor
+///
+/// ```
+/// use serde_java_properties::jasypt::{Algorithm, Jasypt};
+///
+/// let jasypt = Jasypt::new(Algorithm::PbeWithHmacSha512AndAes256, "secret");
+/// let encrypted = jasypt.encrypt("hunter2")?;
+///
+/// assert!(encrypted.starts_with("ENC("));
+/// assert_eq!(jasypt.decrypt_value(&encrypted)?.as_deref(), Some("hunter2"));
+/// assert_eq!(jasypt.decrypt_value("plain")?, None);
+/// # Ok::<(), serde_java_properties::jasypt::Error>(())
+/// ```
+pub struct Jasypt {
+    algorithm: Algorithm,
+    iterations: u32,
+    password: Box<dyn PasswordSource + Send + Sync>,
+}
+
+impl fmt::Debug for Jasypt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Jasypt")
+            .field("algorithm", &self.algorithm)
+            .field("iterations", &self.iterations)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Jasypt {
+    /// Create an encryptor for an algorithm and a password source
+    pub fn new<P>(algorithm: Algorithm, password: P) -> Self
+    where
+        P: PasswordSource + Send + Sync + 'static,
+    {
+        Self {
+            algorithm,
+            iterations: DEFAULT_ITERATIONS,
+            password: Box::new(password),
+        }
+    }
+
+    /// Set the number of key derivation iterations, [`DEFAULT_ITERATIONS`] by default
+    pub fn set_iterations(&mut self, iterations: u32) {
+        self.iterations = iterations;
+    }
+
+    /// Decrypt a value if it has the form `ENC(...)`, or return `None` otherwise
+    pub fn decrypt_value(&self, value: &str) -> Result<Option<String>, Error> {
+        match value
+            .trim()
+            .strip_prefix("ENC(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            Some(encrypted) => self.decrypt(encrypted).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Decrypt the base64 text inside of `ENC(...)`
+    pub fn decrypt(&self, encrypted: &str) -> Result<String, Error> {
+        let bytes = STANDARD.decode(encrypted.trim()).map_err(Error::Base64)?;
+        let block = self.algorithm.block_size();
+        let header = if self.algorithm.has_iv() { 2 } else { 1 } * block;
+        if bytes.len() < header + block || (bytes.len() - header) % block != 0 {
+            return Err(Error::Malformed);
+        }
+        let (salt, rest) = bytes.split_at(block);
+        let (iv, ciphertext) = rest.split_at(header - block);
+        let password = self.password.password().map_err(Error::Password)?;
+        let plain = self.crypt(password.as_bytes(), salt, iv, ciphertext, false)?;
+        String::from_utf8(plain).map_err(Error::Utf8)
+    }
+
+    /// Encrypt a value with a random salt and IV, in the form `ENC(...)`
+    pub fn encrypt(&self, value: &str) -> Result<String, Error> {
+        let block = self.algorithm.block_size();
+        let header = if self.algorithm.has_iv() { 2 } else { 1 } * block;
+        let mut bytes = vec![0; header];
+        getrandom::getrandom(&mut bytes).map_err(Error::Random)?;
+        let (salt, iv) = bytes.split_at(block);
+        let password = self.password.password().map_err(Error::Password)?;
+        let ciphertext = self.crypt(password.as_bytes(), salt, iv, value.as_bytes(), true)?;
+        bytes.extend(ciphertext);
+        Ok(format!("ENC({})", STANDARD.encode(bytes)))
+    }
+
+    fn crypt(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        iv: &[u8],
+        data: &[u8],
+        encrypt: bool,
+    ) -> Result<Vec<u8>, Error> {
+        let iterations = self.iterations;
+        let mut key = [0; 32];
+        match self.algorithm {
+            Algorithm::PbeWithMd5AndDes if !password.iter().all(|b| (b' '..=b'~').contains(b)) => {
+                Err(Error::NonAsciiPassword(self.algorithm))
+            }
+            Algorithm::PbeWithMd5AndDes => {
+                // PBKDF1: the first half of the digest is the key, the second half the IV
+                let mut digest = Md5::new_with_prefix(password).chain_update(salt).finalize();
+                for _ in 1..iterations {
+                    digest = Md5::digest(digest);
+                }
+                let (key, iv) = digest.split_at(8);
+                cbc_crypt::<Des>(key, iv, data, encrypt)
+            }
+            Algorithm::PbeWithHmacSha1AndAes128 | Algorithm::PbeWithHmacSha1AndAes256 => {
+                pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, iterations, &mut key);
+                aes_crypt(self.algorithm, &key, iv, data, encrypt)
+            }
+            Algorithm::PbeWithHmacSha256AndAes128 | Algorithm::PbeWithHmacSha256AndAes256 => {
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut key);
+                aes_crypt(self.algorithm, &key, iv, data, encrypt)
+            }
+            Algorithm::PbeWithHmacSha512AndAes128 | Algorithm::PbeWithHmacSha512AndAes256 => {
+                pbkdf2::pbkdf2_hmac::<Sha512>(password, salt, iterations, &mut key);
+                aes_crypt(self.algorithm, &key, iv, data, encrypt)
+            }
+        }
+    }
+}
+
+/// Run AES-128 or AES-256, depending on the algorithm
+///
+/// PBKDF2 output is a prefix of the output for a longer key, so `key` always holds
+/// 32 bytes, of which AES-128 uses the first 16.
+fn aes_crypt(
+    algorithm: Algorithm,
+    key: &[u8; 32],
+    iv: &[u8],
+    data: &[u8],
+    encrypt: bool,
+) -> Result<Vec<u8>, Error> {
+    match algorithm {
+        Algorithm::PbeWithHmacSha1AndAes128
+        | Algorithm::PbeWithHmacSha256AndAes128
+        | Algorithm::PbeWithHmacSha512AndAes128 => {
+            cbc_crypt::<Aes128>(&key[..16], iv, data, encrypt)
+        }
+        _ => cbc_crypt::<Aes256>(key, iv, data, encrypt),
+    }
+}
+
+fn cbc_crypt<C>(key: &[u8], iv: &[u8], data: &[u8], encrypt: bool) -> Result<Vec<u8>, Error>
+where
+    C: cbc::cipher::BlockCipher
+        + cbc::cipher::BlockEncryptMut
+        + cbc::cipher::BlockDecryptMut
+        + cbc::cipher::KeyInit,
+{
+    if encrypt {
+        let encryptor =
+            cbc::Encryptor::<C>::new_from_slices(key, iv).map_err(|_| Error::Malformed)?;
+        Ok(encryptor.encrypt_padded_vec_mut::<Pkcs7>(data))
+    } else {
+        let decryptor =
+            cbc::Decryptor::<C>::new_from_slices(key, iv).map_err(|_| Error::Malformed)?;
+        decryptor
+            .decrypt_padded_vec_mut::<Pkcs7>(data)
+            .map_err(|_| Error::Decrypt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Algorithm, Error, Jasypt};
+
+    #[test]
+    fn test_roundtrip() {
+        for algorithm in Algorithm::ALL {
+            let jasypt = Jasypt::new(algorithm, "password");
+            let encrypted = jasypt.encrypt("hunter2 ✓").unwrap();
+            let decrypted = jasypt.decrypt_value(&encrypted).unwrap();
+            assert_eq!(decrypted.as_deref(), Some("hunter2 ✓"), "{algorithm}");
+
+            let wrong = Jasypt::new(algorithm, "wrong");
+            assert!(!matches!(wrong.decrypt_value(&encrypted), Ok(Some(v)) if v == "hunter2 ✓"));
+        }
+        assert_eq!(
+            Algorithm::from_name("pbewithhmacsha512andaes_256"),
+            Some(Algorithm::PbeWithHmacSha512AndAes256)
+        );
+    }
+
+    /// Known answers, computed with `openssl enc` and `openssl kdf`
+    #[test]
+    fn test_known_answers() {
+        // PBKDF1 with a single iteration is OpenSSL's `EVP_BytesToKey`
+        let mut jasypt = Jasypt::new(Algorithm::PbeWithMd5AndDes, "password");
+        jasypt.set_iterations(1);
+        assert_eq!(jasypt.decrypt("AQIDBAUGBwj6R4dzl89rhw==").unwrap(), "hello");
+
+        let jasypt = Jasypt::new(Algorithm::PbeWithHmacSha512AndAes256, "password");
+        let encrypted = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh+x57MxTBD2XlbeeqfuQt+4";
+        assert_eq!(jasypt.decrypt(encrypted).unwrap(), "hello");
+    }
+
+    /// Known answers, computed on a JVM with the ciphers and layout of Jasypt's
+    /// `StandardPBEByteEncryptor`, and its default of 1000 iterations
+    #[test]
+    fn test_jvm_known_answers() {
+        let jasypt = Jasypt::new(Algorithm::PbeWithMd5AndDes, "password");
+        let encrypted = "7uE0uvkPQ7aBWuEiqvXYY4l7s5iKcikhNsXvx7KBauRDH1kSeSBkLQ==";
+        assert_eq!(
+            jasypt.decrypt(encrypted).unwrap(),
+            "jdbc:postgresql://db/app"
+        );
+
+        let jasypt = Jasypt::new(Algorithm::PbeWithHmacSha512AndAes256, "password");
+        let encrypted = "agJs2I/eLy0ynyDH15yTw/XZlPQZBiSjelTqEqCNfy1zigjh4Rqc8mEIPDukAxwJCmc9r3t9RqHOmq+7ctQ+gw==";
+        assert_eq!(
+            jasypt.decrypt(encrypted).unwrap(),
+            "jdbc:postgresql://db/app"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let jasypt = Jasypt::new(Algorithm::PbeWithMd5AndDes, "password");
+        assert!(matches!(jasypt.decrypt("AAAA"), Err(Error::Malformed)));
+        assert!(matches!(
+            jasypt.decrypt("not base64!"),
+            Err(Error::Base64(_))
+        ));
+        // The salt is the first 8 bytes, followed by a block of padding
+        let encrypted = jasypt.encrypt("").unwrap();
+        assert_eq!(encrypted.len(), "ENC()".len() + 24);
+
+        let failing = Jasypt::new(Algorithm::PbeWithMd5AndDes, || Err("no password".into()));
+        assert!(matches!(failing.encrypt("x"), Err(Error::Password(_))));
+
+        // Java rejects the password for PBKDF1, but encodes it as UTF-8 for PBKDF2
+        let control = Jasypt::new(Algorithm::PbeWithMd5AndDes, "pass\tword");
+        assert!(matches!(
+            control.encrypt("x"),
+            Err(Error::NonAsciiPassword(_))
+        ));
+        let non_ascii = Jasypt::new(Algorithm::PbeWithMd5AndDes, "pässword");
+        assert!(matches!(
+            non_ascii.encrypt("x"),
+            Err(Error::NonAsciiPassword(Algorithm::PbeWithMd5AndDes))
+        ));
+        assert!(matches!(
+            non_ascii.decrypt(&encrypted[4..encrypted.len() - 1]),
+            Err(Error::NonAsciiPassword(_))
+        ));
+        let non_ascii = Jasypt::new(Algorithm::PbeWithHmacSha512AndAes256, "pässword");
+        let encrypted = non_ascii.encrypt("x").unwrap();
+        assert_eq!(non_ascii.decrypt_value(&encrypted).unwrap().unwrap(), "x");
+    }
+}
@@ -0,0 +1,281 @@
+//! JVM system property arguments
+
+use encoding_rs::UTF_8;
+use java_properties::LineContent::{Comment, KVPair};
+use java_properties::PropertiesIter;
+use serde::{Deserialize, Serialize};
+
+use crate::de::{self, Entry};
+use crate::ser;
+
+/// Turn JVM `-Dkey=value` arguments into a value of `T`
+///
+/// Other arguments are ignored, and `-Dkey` without a `=` sets `key` to the empty string,
+/// as in Java. The key and the value are split at the first `=`, and then unescaped
+/// and typed like a `key=value` line in a properties file, so `-Dname=caf\u00e9` reads
+/// as `café` and a Windows path needs its backslashes doubled, as in `-Dpath=C:\\dir`.
+///
+/// The arguments have no line numbers, so errors name the key instead.
+///
+/// ```
+/// # use serde::Deserialize;
+/// #
+/// #[derive(Deserialize)]
+/// struct Server {
+///     #[serde(rename = "server.port")]
+///     port: u16,
+///     #[serde(rename = "feature.x")]
+///     feature_x: bool,
+/// }
+///
+/// let args = ["-Xmx1g", "-Dserver.port=8080", "-Dfeature.x=true", "-jar", "app.jar"];
+/// let server: Server = serde_java_properties::from_jvm_args(args)?;
+///
+/// assert_eq!(server.port, 8080);
+/// assert!(server.feature_x);
+/// # Ok::<(), serde_java_properties::de::Error>(())
+/// ```
+pub fn from_jvm_args<'a, T, I>(args: I) -> Result<T, de::Error>
+where
+    T: Deserialize<'a>,
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut entries = Vec::new();
+    for arg in args {
+        let Some(property) = arg.as_ref().strip_prefix("-D") else {
+            continue;
+        };
+        let (key, value) = property.split_once('=').unwrap_or((property, ""));
+        let unescape = |text: &str| {
+            unescape(text).ok_or_else(|| de::Error::InvalidValue {
+                value: arg.as_ref().to_string(),
+                expected: "an argument with valid `\\uXXXX` escapes",
+                key: Some(key.to_string()),
+                line: None,
+                source: Box::new(de::Error::Custom {
+                    msg: "malformed `\\uXXXX` escape".to_string(),
+                }),
+            })
+        };
+        entries.push(Entry {
+            line: 0,
+            key: unescape(key)?,
+            value: unescape(value)?,
+            source: None,
+            raw: Some(value.to_string()),
+        });
+    }
+    T::deserialize(de::Deserializer::from_entries(entries))
+}
+
+/// Unescape `text` like the value of a line in a properties file
+///
+/// This returns `None` for a malformed `\uXXXX` escape.
+fn unescape(text: &str) -> Option<String> {
+    // A line break would end the line. The final one ends a line continuation at the
+    // end of `text`, which Java ignores.
+    let line = format!("_={}\n", text.replace('\n', "\\n").replace('\r', "\\r"));
+    match PropertiesIter::new_with_encoding(line.as_bytes(), UTF_8).next()? {
+        Ok(line) => match line.consume_content() {
+            KVPair(_, value) => Some(value),
+            Comment(_) => unreachable!("the line starts with a key"),
+        },
+        Err(_) => None,
+    }
+}
+
+/// Escape `text` so that [`unescape`] turns it back into `text`
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            // Leading whitespace would be skipped
+            ' ' | '\t' | '\x0c' if i == 0 => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Turn a value into JVM `-Dkey=value` arguments
+///
+/// This accepts the same structures as [`crate::Serializer`]. The arguments are meant
+/// to be passed to a process as they are, e.g. with [`std::process::Command::args`].
+/// See [`to_jvm_shell_args`] for a launch command in a shell script.
+///
+/// Keys and values are escaped where [`from_jvm_args`] would otherwise change them:
+/// backslashes are doubled, and line breaks and leading whitespace are escaped.
+///
+/// ```
+/// # use serde::Serialize;
+/// #
+/// #[derive(Serialize)]
+/// struct App {
+///     #[serde(rename = "server.port")]
+///     port: u16,
+///     greeting: String,
+/// }
+///
+/// let app = App { port: 8080, greeting: "it's me".to_string() };
+/// let args = serde_java_properties::to_jvm_args(&app)?;
+///
+/// assert_eq!(args, ["-Dserver.port=8080", "-Dgreeting=it's me"]);
+/// # Ok::<(), serde_java_properties::ser::Error>(())
+/// ```
+pub fn to_jvm_args<T: Serialize>(value: &T) -> Result<Vec<String>, ser::Error> {
+    let pairs = ser::to_pairs(value)?;
+    Ok(pairs
+        .into_iter()
+        .map(|(key, value)| format!("-D{}={}", escape(&key), escape(&value)))
+        .collect())
+}
+
+/// Turn a value into JVM `-Dkey=value` arguments for a POSIX shell
+///
+/// The arguments of [`to_jvm_args`] are quoted where necessary and joined with spaces.
+///
+/// ```
+/// # use serde::Serialize;
+/// #
+/// #[derive(Serialize)]
+/// struct App {
+///     #[serde(rename = "server.port")]
+///     port: u16,
+///     greeting: String,
+/// }
+///
+/// let app = App { port: 8080, greeting: "it's me".to_string() };
+/// let args = serde_java_properties::to_jvm_shell_args(&app)?;
+///
+/// assert_eq!(args, r"-Dserver.port=8080 '-Dgreeting=it'\''s me'");
+/// # Ok::<(), serde_java_properties::ser::Error>(())
+/// ```
+pub fn to_jvm_shell_args<T: Serialize>(value: &T) -> Result<String, ser::Error> {
+    let args = to_jvm_args(value)?;
+    Ok(args
+        .iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Quote an argument for a POSIX shell, if necessary
+fn quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_.,:/@%+=".contains(c);
+    if arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{from_jvm_args, quote, to_jvm_args, to_jvm_shell_args};
+    use crate::de::Error;
+
+    #[test]
+    fn test_roundtrip() {
+        let args = [
+            "java",
+            "-Da.b=1",
+            "-Dflag",
+            r"-Dpath=C:\\dir",
+            "-Dx=a=b",
+            "-cp",
+        ];
+        let map: BTreeMap<String, String> = from_jvm_args(args).unwrap();
+        let expected = [
+            ("a.b", "1"),
+            ("flag", ""),
+            ("path", r"C:\dir"),
+            ("x", "a=b"),
+        ];
+        assert_eq!(
+            map.iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(
+            to_jvm_args(&map).unwrap(),
+            ["-Da.b=1", "-Dflag=", r"-Dpath=C:\\dir", "-Dx=a=b"]
+        );
+
+        // Values that need quoting in a shell or escaping in a properties file
+        let map: BTreeMap<String, String> = [
+            ("greeting", "it's me"),
+            ("home", "$HOME"),
+            ("indent", "  two\nlines\\"),
+            ("path", r"C:\Program Files\app"),
+            ("unicode", r"caf\u00e9 é"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .into();
+        let args = to_jvm_args(&map).unwrap();
+        assert_eq!(args[0], "-Dgreeting=it's me");
+        assert_eq!(args[2], r"-Dindent=\  two\nlines\\");
+        assert_eq!(
+            from_jvm_args::<BTreeMap<String, String>, _>(&args).unwrap(),
+            map
+        );
+        assert_eq!(
+            to_jvm_shell_args(&map).unwrap(),
+            r"'-Dgreeting=it'\''s me' '-Dhome=$HOME' '-Dindent=\  two\nlines\\' '-Dpath=C:\\Program Files\\app' '-Dunicode=caf\\u00e9 é'"
+        );
+    }
+
+    #[test]
+    fn test_unescape() {
+        // The same as `k=...` lines in a properties file
+        let args = [
+            r"-Da=a\u0041",
+            r"-Db=a\nb",
+            "-Dc=  spaced",
+            r"-Dd=trailing\",
+            r"-De\ f=\t",
+        ];
+        let map: BTreeMap<String, String> = from_jvm_args(args).unwrap();
+        for arg in args {
+            let line: BTreeMap<String, String> =
+                crate::from_str(&format!("{}\n", &arg[2..])).unwrap();
+            let (key, value) = line.into_iter().next().unwrap();
+            assert_eq!(map[&key], value);
+        }
+        assert_eq!(map["a"], "aA");
+        assert_eq!(map["b"], "a\nb");
+        assert_eq!(map["c"], "spaced");
+        assert_eq!(map["d"], "trailing");
+        assert_eq!(map["e f"], "\t");
+    }
+
+    #[test]
+    fn test_errors() {
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(dead_code)]
+        struct Port {
+            port: u16,
+        }
+        let err = from_jvm_args::<Port, _>(["-Xmx1g", "-Dport=http"]).unwrap_err();
+        assert!(matches!(err, Error::InvalidValue { .. }), "{err}");
+        // There is no line to point to
+        assert_eq!(err.line(), None);
+        assert_eq!(
+            err.to_string(),
+            "invalid value \"http\" for `port`: invalid digit found in string"
+        );
+
+        let err = from_jvm_args::<Port, _>([r"-Dport=\u12"]).unwrap_err();
+        assert!(matches!(err, Error::InvalidValue { .. }), "{err}");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+    }
+}
@@ -134,6 +134,55 @@
 //! assert_eq!(out, "light=On\n");
 //! ```
 //!
+//! ## Validation
+//!
+//! [`validate`] works like [`from_str`], but doesn't stop at the first error. Instead, it
+//! returns a [`de::Report`] with every rejected key or value and every missing field,
+//! along with their line numbers.
+//!
+//! With the `miette` feature enabled, [`de::Report::with_source`] attaches the input to
+//! the report, so that it can be rendered with a snippet of each offending line and a hint
+//! about the expected values. [`de::Error::with_source`] does the same for the single
+//! error that [`from_str`] returns.
+//!
+//! ## Environment variables
+//!
+//! [`Deserializer::set_env_overlay`] overrides values with environment variables named after
+//! their keys, e.g. `MYAPP_DB_POOL_MAX` for `db.pool.max`, and [`env::to_env_vars`] turns a
+//! value into such variables, e.g. for deployment manifests.
+//!
+//! ## Encrypted values
+//!
+//! With the `jasypt` feature enabled, [`Deserializer::set_jasypt`] decrypts values of the
+//! form `ENC(...)` written by [Jasypt](http://www.jasypt.org/), and
+//! [`Serializer::encrypt_fields`] encrypts chosen fields in the same format.
+//!
+//! ## `java.util.Properties`
+//!
+//! [`Properties`] mirrors `java.util.Properties`: it keeps the order of its keys, falls
+//! back to a chain of defaults on lookups, and can be loaded, stored and deserialized
+//! into a struct.
+//!
+//! ## Migrations
+//!
+//! [`migrate::Migrations`] upgrades files with an older `config.version` to the current
+//! layout, with renamed keys, moved prefixes and transformed values, before they are
+//! deserialized. The upgraded file can be written back, keeping its comments.
+//!
+//! ## Schemas
+//!
+//! With the `schema` feature enabled, [`schema::validate`] checks an input against a
+//! schema that lists the allowed keys, the types of their values, ranges, patterns and
+//! choices. Schemas can be read from properties files, so that the configuration can
+//! be checked without the Rust types behind it.
+//!
+//! ## Untyped values
+//!
+//! [`Value`] holds properties without a fixed structure, keeping the raw text of every
+//! value. Its keys can be nested by their dots, and its values can be read as any type
+//! with [`Value::get`]. With the `serde_json` feature enabled, it converts to and from
+//! `serde_json::Value`.
+//!
 //! ## Alternatives
 //!
 //! Similar to the [`java-properties` crate](https://crates.io/crates/java-properties) itself,
@@ -144,16 +193,29 @@
 //! should probably use [HOCON](https://crates.io/crates/hocon).
 
 pub mod de;
+pub mod env;
+#[cfg(feature = "jasypt")]
+pub mod jasypt;
+mod jvm;
+pub mod migrate;
+mod properties;
+#[cfg(feature = "schema")]
+pub mod schema;
 pub mod ser;
+mod value;
 
 use std::io::{self, Read};
 
 pub use de::Deserializer;
 use encoding_rs::UTF_8;
+pub use jvm::{from_jvm_args, to_jvm_args, to_jvm_shell_args};
+pub use properties::Properties;
 pub use ser::Serializer;
+pub use value::{Value, ValueDeserializer};
 
 use de::Error;
 use encoding_rs::Encoding;
+use indexmap::IndexMap;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 /// Turn a string into a value of `T`
@@ -163,6 +225,71 @@ pub fn from_str<'a, T: Deserialize<'a>>(input: &'a str) -> Result<T, Error> {
     T::deserialize(de::Deserializer::from_str(input))
 }
 
+/// Turn the keys of a string that start with `prefix` into a value of `T`
+///
+/// The prefix is removed from the keys, and other keys are ignored. See
+/// [`Deserializer::set_prefix`].
+///
+/// ```
+/// # use serde::Deserialize;
+/// #
+/// #[derive(Deserialize)]
+/// struct DataSource {
+///     url: String,
+///     username: String,
+/// }
+///
+/// let text = "
+/// spring.datasource.url=jdbc:postgresql://db/app
+/// spring.datasource.username=app
+/// kafka.consumer.group-id=app
+/// ";
+/// let ds: DataSource =
+///     serde_java_properties::from_str_with_prefix(text, "spring.datasource.").unwrap();
+///
+/// assert_eq!(ds.username, "app");
+/// ```
+pub fn from_str_with_prefix<'a, T: Deserialize<'a>>(
+    input: &'a str,
+    prefix: &str,
+) -> Result<T, Error> {
+    let mut de = de::Deserializer::from_str(input);
+    de.set_prefix(prefix);
+    T::deserialize(de)
+}
+
+/// Turn a string into a value of `T`, along with the keys that no field used
+///
+/// Instead of being dropped silently, every key that `T` ignored is returned as a
+/// [`de::Warning::UnusedKey`] with its line number, e.g. to be logged at startup.
+/// See [`Deserializer::set_warn_unused_keys`].
+///
+/// ```
+/// # use serde::Deserialize;
+/// #
+/// #[derive(Deserialize)]
+/// struct Server {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// let text = "host=localhost\nprot=8080\nport=80\n";
+/// let (server, unused): (Server, _) =
+///     serde_java_properties::from_str_with_report(text).unwrap();
+///
+/// assert_eq!(server.port, 80);
+/// assert_eq!(unused.len(), 1);
+/// assert_eq!(unused[0].to_string(), "line 2: unused key `prot`");
+/// ```
+pub fn from_str_with_report<'a, T: Deserialize<'a>>(
+    input: &'a str,
+) -> Result<(T, Vec<de::Warning>), Error> {
+    let mut de = de::Deserializer::from_str(input);
+    de.set_warn_unused_keys(true);
+    let value = T::deserialize(&mut de)?;
+    Ok((value, de.take_warnings()))
+}
+
 /// Turn a byte slice into a value of `T`
 ///
 /// This should technically be `T: DeserializeOwned`, but the implementation may change in the future
@@ -201,6 +328,73 @@ pub fn from_reader_with_encoding<T: DeserializeOwned, R: Read>(
     ))
 }
 
+/// Turn key-value pairs, e.g. a [`HashMap`](std::collections::HashMap), into a value of `T`
+///
+/// The values are typed like values in a properties file. They are not unescaped, and
+/// errors don't have line numbers.
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # use serde::Deserialize;
+/// #
+/// #[derive(Deserialize)]
+/// struct Server {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// let map = HashMap::from([
+///     ("host".to_string(), "localhost".to_string()),
+///     ("port".to_string(), "8080".to_string()),
+/// ]);
+/// let server: Server = serde_java_properties::from_map(map).unwrap();
+///
+/// assert_eq!(server.port, 8080);
+/// ```
+pub fn from_map<'a, T, I>(map: I) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+    I: IntoIterator<Item = (String, String)>,
+{
+    let entries = map
+        .into_iter()
+        .map(|(key, value)| de::Entry {
+            line: 0,
+            key,
+            value,
+            source: None,
+            raw: None,
+        })
+        .collect();
+    T::deserialize(de::Deserializer::from_entries(entries))
+}
+
+/// Turn a value into a map from keys to values, in the order they were serialized
+///
+/// This accepts the same structures as [`Serializer`], but the keys and values are
+/// neither encoded nor escaped.
+///
+/// ```
+/// # use serde::Serialize;
+/// #
+/// #[derive(Serialize)]
+/// struct Server {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// let server = Server { host: "::1".to_string(), port: 8080 };
+/// let map = serde_java_properties::to_map(&server).unwrap();
+///
+/// assert_eq!(map["host"], "::1");
+/// assert_eq!(map.get_index(1), Some((&"port".to_string(), &"8080".to_string())));
+/// ```
+pub fn to_map<T: Serialize>(value: &T) -> Result<IndexMap<String, String>, ser::Error> {
+    let mut map = IndexMap::new();
+    value.serialize(Serializer::from_sink(&mut map))?;
+    Ok(map)
+}
+
 /// Write a properties file to a string
 ///
 /// *Important*: This uses UTF-8 encoding as the result is a Rust [String]
@@ -249,3 +443,32 @@ pub fn to_writer_with_encoding<T: Serialize, W: io::Write>(
     value.serialize(serializer)?;
     Ok(())
 }
+
+/// Turn a string into a value of `T`, reporting all errors instead of just the first
+///
+/// Unlike [`from_str`], this keeps going after a key or value is rejected, or a required
+/// field is missing, and returns every such error with its line number in a [`de::Report`].
+///
+/// ```
+/// # use serde::Deserialize;
+/// #
+/// #[derive(Debug, Deserialize)]
+/// #[serde(deny_unknown_fields)]
+/// struct Server {
+///     host: String,
+///     port: u16,
+///     workers: usize,
+/// }
+///
+/// let text = "port=http\nworkers=-1\nhots=localhost";
+/// let report = serde_java_properties::validate::<Server>(text).unwrap_err();
+///
+/// assert_eq!(report.diagnostics.len(), 4);
+/// assert_eq!(report.diagnostics[0].line, Some(1));
+/// assert_eq!(report.diagnostics[1].line, Some(2));
+/// assert_eq!(report.diagnostics[2].line, Some(3));
+/// assert_eq!(report.diagnostics[3].key.as_deref(), Some("host"));
+/// ```
+pub fn validate<'a, T: Deserialize<'a>>(input: &'a str) -> Result<T, de::Report> {
+    de::validate(|| de::Deserializer::from_str(input))
+}
@@ -0,0 +1,688 @@
+//! Upgrading configuration files to a newer layout
+//!
+//! A file records the version of its layout in the `config.version` key. Every
+//! [`Migration`] upgrades files to its version with an ordered list of steps, such as
+//! renaming keys, moving a prefix, transforming values, splitting a value into
+//! several keys and deleting keys. [`Migrations`] applies every migration that is
+//! newer than a file in order, and sets `config.version` to the newest version.
+//!
+//! Files without `config.version` are at version 0, so files that are written for
+//! the current layout should set it.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io, mem};
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use java_properties::{LineContent, PropertiesError, PropertiesIter, PropertiesWriter};
+
+use crate::de::{self, Deserializer, Entry, TransformError};
+
+/// The key that holds the version of a file
+pub const VERSION_KEY: &str = "config.version";
+
+type Transform = Box<dyn Fn(&str) -> Result<String, TransformError> + Send + Sync>;
+type Split = Box<dyn Fn(&str) -> Result<Vec<(String, String)>, TransformError> + Send + Sync>;
+
+/// A step of a [`Migration`]
+enum Step {
+    Rename { from: String, to: String },
+    MovePrefix { from: String, to: String },
+    Transform { key: String, transform: Transform },
+    Split { key: String, split: Split },
+    Delete { key: String },
+}
+
+/// The steps that upgrade a file to a version
+///
+/// Steps are applied in the order they were added.
+pub struct Migration {
+    version: u32,
+    steps: Vec<Step>,
+}
+
+impl Migration {
+    /// Create a migration to `version` without any steps
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            steps: Vec::new(),
+        }
+    }
+
+    /// The version that this migration upgrades to
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Rename the key `from` to `to`
+    pub fn rename(&mut self, from: impl Into<String>, to: impl Into<String>) {
+        self.steps.push(Step::Rename {
+            from: from.into(),
+            to: to.into(),
+        });
+    }
+
+    /// Replace the prefix `from` of every key with `to`
+    pub fn move_prefix(&mut self, from: impl Into<String>, to: impl Into<String>) {
+        self.steps.push(Step::MovePrefix {
+            from: from.into(),
+            to: to.into(),
+        });
+    }
+
+    /// Replace the value of `key`, e.g. to change its unit
+    pub fn transform<F>(&mut self, key: impl Into<String>, transform: F)
+    where
+        F: Fn(&str) -> Result<String, TransformError> + Send + Sync + 'static,
+    {
+        self.steps.push(Step::Transform {
+            key: key.into(),
+            transform: Box::new(transform),
+        });
+    }
+
+    /// Replace `key` with the keys and values that `split` returns for its value
+    pub fn split<F>(&mut self, key: impl Into<String>, split: F)
+    where
+        F: Fn(&str) -> Result<Vec<(String, String)>, TransformError> + Send + Sync + 'static,
+    {
+        self.steps.push(Step::Split {
+            key: key.into(),
+            split: Box::new(split),
+        });
+    }
+
+    /// Remove `key`
+    pub fn delete(&mut self, key: impl Into<String>) {
+        self.steps.push(Step::Delete { key: key.into() });
+    }
+
+    fn apply(&self, items: &mut Vec<Item>) -> Result<(), Error> {
+        for step in &self.steps {
+            let mut migrated = Vec::with_capacity(items.len());
+            // The old key, line and new key of the entries that the step gave a new key
+            let mut moved = Vec::new();
+            // The lines of the keys that the step left alone
+            let mut kept = HashMap::new();
+            for item in items.drain(..) {
+                let mut entry = match item {
+                    Item::Entry(entry) => entry,
+                    comment => {
+                        migrated.push(comment);
+                        continue;
+                    }
+                };
+                let failed = |source| Error::Step {
+                    version: self.version,
+                    key: entry.key.clone(),
+                    line: entry.line,
+                    source,
+                };
+                let renamed = match step {
+                    Step::Rename { from, to } if entry.key == *from => {
+                        Some(mem::replace(&mut entry.key, to.clone()))
+                    }
+                    Step::MovePrefix { from, to } if entry.key.starts_with(from.as_str()) => {
+                        let key = format!("{}{}", to, &entry.key[from.len()..]);
+                        Some(mem::replace(&mut entry.key, key))
+                    }
+                    Step::Transform { key, transform } if entry.key == *key => {
+                        entry.value = transform(&entry.value).map_err(failed)?;
+                        None
+                    }
+                    Step::Split { key, split } if entry.key == *key => {
+                        let pairs = split(&entry.value).map_err(failed)?;
+                        for (key, value) in pairs {
+                            moved.push((entry.key.clone(), entry.line, key.clone()));
+                            migrated.push(Item::Entry(Entry {
+                                key,
+                                value,
+                                ..entry.clone()
+                            }));
+                        }
+                        continue;
+                    }
+                    Step::Delete { key } if entry.key == *key => continue,
+                    _ => None,
+                };
+                match renamed {
+                    Some(old) => moved.push((old, entry.line, entry.key.clone())),
+                    None => {
+                        kept.insert(entry.key.clone(), entry.line);
+                    }
+                }
+                migrated.push(Item::Entry(entry));
+            }
+            for (old, line, key) in moved {
+                if let Some(existing) = kept.get(&key) {
+                    return Err(Error::Step {
+                        version: self.version,
+                        key: old,
+                        line,
+                        source: format!("`{}` is already set on line {}", key, existing).into(),
+                    });
+                }
+            }
+            *items = migrated;
+        }
+        Ok(())
+    }
+}
+
+/// A line of a file
+#[derive(Debug)]
+enum Item {
+    Blank,
+    Comment(String),
+    Entry(Entry),
+}
+
+/// Upgrades files to the newest version of their layout
+///
+/// ```
+/// # use serde::Deserialize;
+/// use serde_java_properties::migrate::{Migration, Migrations};
+///
+/// #[derive(Deserialize)]
+/// struct Db {
+///     #[serde(rename = "db.pool.max")]
+///     max: usize,
+///     #[serde(rename = "db.timeout.ms")]
+///     timeout: u64,
+/// }
+///
+/// let mut v1 = Migration::new(1);
+/// v1.rename("db.maxconn", "db.pool.max");
+/// let mut v2 = Migration::new(2);
+/// v2.rename("db.timeout.s", "db.timeout.ms");
+/// v2.transform("db.timeout.ms", |seconds: &str| {
+///     Ok(format!("{}000", seconds))
+/// });
+///
+/// let mut migrations = Migrations::new();
+/// migrations.add(v1);
+/// migrations.add(v2);
+///
+/// let migrated = migrations.migrate_str("# Production\ndb.maxconn=20\ndb.timeout.s=5\n")?;
+/// assert_eq!((migrated.from_version(), migrated.version()), (0, 2));
+/// assert_eq!(migrated.to_string(), "\
+/// ## Production
+/// config.version=2
+/// db.pool.max=20
+/// db.timeout.ms=5000
+/// ");
+///
+/// let db = Db::deserialize(migrated.deserializer())?;
+/// assert_eq!((db.max, db.timeout), (20, 5000));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Migrations {
+    migrations: Vec<Migration>,
+    encoding: &'static Encoding,
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Migrations {
+    /// Create an empty list of migrations
+    ///
+    /// Readers and files are read and written as *ISO-8859-1* by default.
+    pub fn new() -> Self {
+        Self {
+            migrations: Vec::new(),
+            encoding: WINDOWS_1252,
+        }
+    }
+
+    /// Set the encoding of readers and files
+    pub fn set_encoding(&mut self, encoding: &'static Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Add a migration
+    ///
+    /// Migrations are applied in the order of their versions.
+    pub fn add(&mut self, migration: Migration) {
+        let i = self
+            .migrations
+            .partition_point(|m| m.version <= migration.version);
+        self.migrations.insert(i, migration);
+    }
+
+    /// The newest version, which files are upgraded to
+    pub fn current_version(&self) -> u32 {
+        self.migrations.last().map_or(0, |m| m.version)
+    }
+
+    /// Upgrade a string
+    pub fn migrate_str(&self, text: &str) -> Result<Migrated, Error> {
+        self.migrate(text.as_bytes(), UTF_8)
+    }
+
+    /// Upgrade the contents of a [`io::Read`] implementation
+    pub fn migrate_reader<R: io::Read>(&self, reader: R) -> Result<Migrated, Error> {
+        self.migrate(reader, self.encoding)
+    }
+
+    /// Upgrade a file, and write it back if `write_back` is set and it changed
+    ///
+    /// Comments and blank lines are kept, but the other lines are written in a
+    /// normalized form.
+    pub fn migrate_path(
+        &self,
+        path: impl AsRef<Path>,
+        write_back: bool,
+    ) -> Result<Migrated, Error> {
+        let path = path.as_ref();
+        let io_error = |source| Error::Io {
+            path: path.to_owned(),
+            source,
+        };
+        let file = fs::File::open(path).map_err(io_error)?;
+        let migrated = self.migrate(io::BufReader::new(file), self.encoding)?;
+        if write_back && migrated.is_changed() {
+            let mut out = Vec::new();
+            migrated.write(&mut out)?;
+            fs::write(path, out).map_err(io_error)?;
+        }
+        Ok(migrated)
+    }
+
+    fn migrate<R: io::Read>(
+        &self,
+        mut reader: R,
+        encoding: &'static Encoding,
+    ) -> Result<Migrated, Error> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|e| Error::Read(e.into()))?;
+        let (text, _, _) = encoding.decode(&bytes);
+        // The parser skips blank lines, so they are found in the text
+        let mut blank = blank_lines(&text).into_iter().peekable();
+        let mut items = Vec::new();
+        for line in PropertiesIter::new_with_encoding(text.as_bytes(), UTF_8) {
+            let line = line.map_err(|e| Error::Read(e.into()))?;
+            let line_number = line.line_number();
+            while blank.next_if(|&n| n < line_number).is_some() {
+                items.push(Item::Blank);
+            }
+            items.push(match line.consume_content() {
+                LineContent::Comment(text) => Item::Comment(text),
+                LineContent::KVPair(key, value) => Item::Entry(Entry {
+                    line: line_number,
+                    key,
+                    value,
+                    source: None,
+                    raw: None,
+                }),
+            });
+        }
+        items.extend(blank.map(|_| Item::Blank));
+        let version = items.iter().rev().find_map(|item| match item {
+            Item::Entry(entry) if entry.key == VERSION_KEY => Some(entry),
+            _ => None,
+        });
+        let from = match version {
+            None => 0,
+            Some(entry) => entry
+                .value
+                .trim()
+                .parse()
+                .map_err(|_| Error::InvalidVersion {
+                    value: entry.value.clone(),
+                    line: entry.line,
+                })?,
+        };
+        let current = self.current_version();
+        if from > current {
+            return Err(Error::UnsupportedVersion {
+                version: from,
+                current,
+            });
+        }
+        for migration in self.migrations.iter().filter(|m| m.version > from) {
+            migration.apply(&mut items)?;
+        }
+        if from < current {
+            set_version(&mut items, current);
+        }
+        Ok(Migrated {
+            items,
+            from,
+            to: current.max(from),
+            encoding,
+        })
+    }
+}
+
+/// The 1-based numbers of the lines of `text` that are blank
+///
+/// Lines that continue a value are not blank, even if they are empty.
+fn blank_lines(text: &str) -> Vec<usize> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    let mut blank = Vec::new();
+    let mut continued = false;
+    for (i, line) in text.split('\n').enumerate() {
+        let trimmed = line.trim_start_matches([' ', '\t', '\x0c']);
+        let ends_with_escape = (line.len() - line.trim_end_matches('\\').len()) % 2 == 1;
+        if continued {
+            continued = ends_with_escape;
+        } else if trimmed.is_empty() {
+            blank.push(i + 1);
+        } else {
+            continued = ends_with_escape && !trimmed.starts_with(['#', '!']);
+        }
+    }
+    blank
+}
+
+/// Set `config.version`, adding it after the leading comments if it is missing
+fn set_version(items: &mut Vec<Item>, version: u32) {
+    items.retain(|item| !matches!(item, Item::Entry(entry) if entry.key == VERSION_KEY));
+    let i = (items.iter())
+        .position(|item| matches!(item, Item::Entry(_)))
+        .unwrap_or(items.len());
+    let entry = Entry {
+        line: 0,
+        key: VERSION_KEY.to_string(),
+        value: version.to_string(),
+        source: None,
+        raw: None,
+    };
+    items.insert(i, Item::Entry(entry));
+}
+
+/// An upgraded file
+#[derive(Debug)]
+pub struct Migrated {
+    items: Vec<Item>,
+    from: u32,
+    to: u32,
+    encoding: &'static Encoding,
+}
+
+impl Migrated {
+    /// The version of the file before it was upgraded
+    pub fn from_version(&self) -> u32 {
+        self.from
+    }
+
+    /// The version of the file now
+    pub fn version(&self) -> u32 {
+        self.to
+    }
+
+    /// Whether any migration was applied
+    pub fn is_changed(&self) -> bool {
+        self.from != self.to
+    }
+
+    /// Create a deserializer for the upgraded file
+    ///
+    /// Values keep the line numbers of the original file, and the `config.version`
+    /// key is not passed on to the deserializer.
+    pub fn deserializer(&self) -> Deserializer<io::Empty> {
+        let entries = (self.items.iter())
+            .filter_map(|item| match item {
+                Item::Entry(entry) if entry.key != VERSION_KEY => Some(entry.clone()),
+                _ => None,
+            })
+            .collect();
+        Deserializer::from_entries(entries)
+    }
+
+    /// Write the upgraded file in its original encoding
+    pub fn write<W: io::Write>(&self, writer: W) -> Result<(), Error> {
+        self.write_with_encoding(writer, self.encoding)
+            .map_err(Error::Write)
+    }
+
+    fn write_with_encoding<W: io::Write>(
+        &self,
+        mut writer: W,
+        encoding: &'static Encoding,
+    ) -> Result<(), PropertiesError> {
+        // A `PropertiesWriter` can't write blank lines, so they are written between them
+        for (i, lines) in self
+            .items
+            .split(|item| matches!(item, Item::Blank))
+            .enumerate()
+        {
+            if i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            let mut writer = PropertiesWriter::new_with_encoding(&mut writer, encoding);
+            for item in lines {
+                match item {
+                    Item::Comment(text) => writer.write_comment(text)?,
+                    Item::Entry(entry) => writer.write(&entry.key, &entry.value)?,
+                    Item::Blank => unreachable!(),
+                }
+            }
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Migrated {
+    /// The upgraded file as text
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = Vec::new();
+        self.write_with_encoding(&mut out, UTF_8)
+            .map_err(|_| fmt::Error)?;
+        f.write_str(&String::from_utf8_lossy(&out))
+    }
+}
+
+/// An error while upgrading a file
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The input could not be parsed
+    Read(de::Error),
+    /// The upgraded file could not be written
+    Write(PropertiesError),
+    /// A file could not be read or written
+    Io {
+        /// The path of the file
+        path: PathBuf,
+        /// The cause
+        source: io::Error,
+    },
+    /// The value of `config.version` is not a version number
+    InvalidVersion {
+        /// The value
+        value: String,
+        /// The 1-based line number
+        line: usize,
+    },
+    /// The file is newer than the newest migration
+    UnsupportedVersion {
+        /// The version of the file
+        version: u32,
+        /// The newest version that is known
+        current: u32,
+    },
+    /// A step of a migration rejected a value
+    Step {
+        /// The version of the migration
+        version: u32,
+        /// The key of the value
+        key: String,
+        /// The 1-based line number of the value
+        line: usize,
+        /// The cause
+        source: TransformError,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(e) => e.fmt(f),
+            Self::Write(e) => write!(f, "failed to write the upgraded file: {}", e),
+            Self::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Self::InvalidVersion { value, line } => write!(
+                f,
+                "invalid `{}` on line {}: `{}` is not a version number",
+                VERSION_KEY, line, value
+            ),
+            Self::UnsupportedVersion { version, current } => write!(
+                f,
+                "version {} is newer than the newest known version {}",
+                version, current
+            ),
+            Self::Step {
+                version,
+                key,
+                line,
+                source,
+            } => write!(
+                f,
+                "migration to version {} failed for `{}` on line {}: {}",
+                version, key, line, source
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read(e) => Some(e),
+            Self::Write(e) => Some(e),
+            Self::Io { source, .. } => Some(source),
+            Self::Step { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    use super::{Error, Migration, Migrations};
+
+    fn migrations() -> Migrations {
+        let mut v2 = Migration::new(2);
+        v2.move_prefix("database.", "db.");
+        v2.split("db.url", |url: &str| {
+            let (host, port) = url.split_once(':').ok_or("expected host:port")?;
+            Ok(vec![
+                ("db.host".to_string(), host.to_string()),
+                ("db.port".to_string(), port.to_string()),
+            ])
+        });
+        v2.delete("db.legacy");
+        let mut v1 = Migration::new(1);
+        v1.rename("database.address", "database.url");
+
+        let mut migrations = Migrations::new();
+        migrations.add(v2);
+        migrations.add(v1);
+        migrations
+    }
+
+    #[test]
+    fn test_migrate() {
+        let migrations = migrations();
+        assert_eq!(migrations.current_version(), 2);
+
+        let text = "database.address=db:5432\n\n! old\ndatabase.legacy=x\\\n\ndatabase.user=sa\n\n";
+        let migrated = migrations.migrate_str(text).unwrap();
+        assert_eq!(
+            migrated.to_string(),
+            "config.version=2\ndb.host=db\ndb.port=5432\n\n# old\ndb.user=sa\n\n"
+        );
+        let map = BTreeMap::<String, String>::deserialize(migrated.deserializer()).unwrap();
+        assert_eq!(
+            map.keys().collect::<Vec<_>>(),
+            ["db.host", "db.port", "db.user"]
+        );
+
+        // Only newer migrations are applied
+        let text = "config.version=1\ndatabase.address=db\ndatabase.url=db:1";
+        let migrated = migrations.migrate_str(text).unwrap();
+        assert_eq!(migrated.from_version(), 1);
+        assert_eq!(
+            migrated.to_string(),
+            "config.version=2\ndb.address=db\ndb.host=db\ndb.port=1\n"
+        );
+        let migrated = migrations.migrate_str(&migrated.to_string()).unwrap();
+        assert!(!migrated.is_changed());
+    }
+
+    #[test]
+    fn test_errors() {
+        let migrations = migrations();
+        let err = migrations.migrate_str("config.version=two").unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidVersion { line: 1, .. }),
+            "{err}"
+        );
+        let err = migrations.migrate_str("config.version=3").unwrap_err();
+        assert!(matches!(err, Error::UnsupportedVersion { version: 3, .. }));
+        let err = migrations.migrate_str("a=1\ndatabase.url=db").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "migration to version 2 failed for `db.url` on line 2: expected host:port"
+        );
+
+        // Keys are not renamed onto keys that are already set
+        let err = migrations
+            .migrate_str("database.address=a:1\ndatabase.url=b:2")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "migration to version 1 failed for `database.address` on line 1: \
+             `database.url` is already set on line 2"
+        );
+        let err = migrations
+            .migrate_str("config.version=1\ndatabase.user=a\ndb.user=b")
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Step { version: 2, key, line: 2, .. } if key == "database.user"),
+            "{err}"
+        );
+        let err = migrations
+            .migrate_str("config.version=1\ndb.host=a\ndatabase.url=b:2")
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Step { version: 2, key, line: 3, .. } if key == "db.url"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_write_back() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("app.properties");
+        fs::write(&path, "# Database\n\ndatabase.url=db:5432\n").unwrap();
+
+        let migrations = migrations();
+        let migrated = migrations.migrate_path(&path, false).unwrap();
+        assert!(migrated.is_changed());
+        assert!(fs::read_to_string(&path).unwrap().contains("database.url"));
+
+        migrations.migrate_path(&path, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Database\n\nconfig.version=2\ndb.host=db\ndb.port=5432\n"
+        );
+        let migrated = migrations.migrate_path(&path, true).unwrap();
+        assert_eq!(migrated.from_version(), 2);
+    }
+}
@@ -0,0 +1,289 @@
+//! An ordered collection of properties, like `java.util.Properties`
+
+use std::io;
+
+use encoding_rs::Encoding;
+use indexmap::{IndexMap, IndexSet};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::Serializer as _;
+
+use crate::de::{self, Deserializer, Entry, FieldDeserializer};
+use crate::ser::{self, Serializer};
+
+/// Properties in the order they were set, with optional defaults
+///
+/// Like in `java.util.Properties`, lookups fall back to the defaults for keys that
+/// are not set, and the defaults can have defaults of their own. Storing and
+/// iterating only involve the properties that are set, not the defaults.
+///
+/// ```
+/// use serde::{de::IntoDeserializer, Deserialize};
+/// use serde_java_properties::Properties;
+///
+/// let mut defaults = Properties::new();
+/// defaults.set_property("server.host", "localhost");
+/// defaults.set_property("server.port", "80");
+///
+/// let mut props = Properties::with_defaults(defaults);
+/// props.load("server.port=8080\n".as_bytes())?;
+///
+/// assert_eq!(props.get_property("server.host"), Some("localhost"));
+/// assert_eq!(props.get::<u16>("server.port")?, Some(8080));
+/// assert_eq!(props.len(), 1);
+///
+/// #[derive(Deserialize)]
+/// struct Server {
+///     #[serde(rename = "server.host")]
+///     host: String,
+///     #[serde(rename = "server.port")]
+///     port: u16,
+/// }
+///
+/// let server = Server::deserialize(props.into_deserializer())?;
+/// assert_eq!((server.host.as_str(), server.port), ("localhost", 8080));
+/// # Ok::<(), serde_java_properties::de::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    map: IndexMap<String, String>,
+    defaults: Option<Box<Properties>>,
+}
+
+impl Properties {
+    /// Create empty properties without defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create empty properties that fall back to `defaults`
+    pub fn with_defaults(defaults: Properties) -> Self {
+        Self {
+            map: IndexMap::new(),
+            defaults: Some(Box::new(defaults)),
+        }
+    }
+
+    /// The properties that lookups fall back to
+    pub fn defaults(&self) -> Option<&Properties> {
+        self.defaults.as_deref()
+    }
+
+    /// Replace the properties that lookups fall back to
+    pub fn set_defaults(&mut self, defaults: Option<Properties>) {
+        self.defaults = defaults.map(Box::new);
+    }
+
+    /// Read properties from `reader`, which is expected to be *ISO-8859-1*
+    ///
+    /// Keys that are already set are overwritten, but keep their position.
+    pub fn load<R: io::Read>(&mut self, reader: R) -> Result<(), de::Error> {
+        self.extend_from(Deserializer::from_reader(reader))
+    }
+
+    /// Read properties from `reader` using the given encoding
+    pub fn load_with_encoding<R: io::Read>(
+        &mut self,
+        reader: R,
+        encoding: &'static Encoding,
+    ) -> Result<(), de::Error> {
+        self.extend_from(Deserializer::from_reader_with_encoding(reader, encoding))
+    }
+
+    fn extend_from<R: io::Read>(&mut self, de: Deserializer<R>) -> Result<(), de::Error> {
+        for entry in de.into_entries()? {
+            self.map.insert(entry.key, entry.value);
+        }
+        Ok(())
+    }
+
+    /// Write the properties that are set, without the defaults, as *ISO-8859-1*
+    pub fn store<W: io::Write>(&self, writer: W) -> Result<(), ser::Error> {
+        Serializer::from_writer(writer).collect_map(&self.map)
+    }
+
+    /// Write the properties that are set, without the defaults, using the given encoding
+    pub fn store_with_encoding<W: io::Write>(
+        &self,
+        writer: W,
+        encoding: &'static Encoding,
+    ) -> Result<(), ser::Error> {
+        Serializer::from_writer_with_encoding(writer, encoding).collect_map(&self.map)
+    }
+
+    /// Look up `key`, falling back to the defaults
+    pub fn get_property(&self, key: &str) -> Option<&str> {
+        match self.map.get(key) {
+            Some(value) => Some(value),
+            None => self.defaults.as_ref()?.get_property(key),
+        }
+    }
+
+    /// Look up `key`, falling back to the defaults and then to `default`
+    pub fn get_property_or<'a>(&'a self, key: &str, default: &'a str) -> &'a str {
+        self.get_property(key).unwrap_or(default)
+    }
+
+    /// Look up `key` like [`Properties::get_property`], and parse its value as a `T`
+    ///
+    /// Values are parsed like in a properties file, so an empty value is `None` for
+    /// an `Option`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, de::Error> {
+        self.get_property(key)
+            .map(|value| T::deserialize(FieldDeserializer(value.to_string())))
+            .transpose()
+    }
+
+    /// Set `key` to `value`, returning the previous value
+    ///
+    /// The defaults are not changed.
+    pub fn set_property(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Option<String> {
+        self.map.insert(key.into(), value.into())
+    }
+
+    /// Remove `key`, keeping the order of the other keys
+    ///
+    /// The defaults are not changed, so `key` may still be found in them.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.map.shift_remove(key)
+    }
+
+    /// The keys that are set here or in the defaults
+    ///
+    /// Keys that are set here come first, followed by the keys that are only in the
+    /// defaults.
+    pub fn string_property_names(&self) -> IndexSet<&str> {
+        let mut names: IndexSet<&str> = self.map.keys().map(String::as_str).collect();
+        if let Some(defaults) = &self.defaults {
+            names.extend(defaults.string_property_names());
+        }
+        names
+    }
+
+    /// Iterate over the properties that are set, without the defaults
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.map
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// The number of properties that are set, without the defaults
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Whether no properties are set, ignoring the defaults
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Properties {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            map: iter
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+            defaults: None,
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, de::Error> for Properties {
+    type Deserializer = Deserializer<io::Empty>;
+
+    /// Deserialize the properties with their defaults, in the order of
+    /// [`Properties::string_property_names`]
+    fn into_deserializer(self) -> Self::Deserializer {
+        let entries = self
+            .string_property_names()
+            .into_iter()
+            .map(|key| Entry {
+                line: 0,
+                key: key.to_string(),
+                value: self.get_property(key).unwrap_or_default().to_string(),
+                source: None,
+                raw: None,
+            })
+            .collect();
+        Deserializer::from_entries(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::de::IntoDeserializer;
+    use serde::Deserialize;
+
+    use super::Properties;
+
+    #[test]
+    fn test_defaults_chain() {
+        let base: Properties = [("a", "base"), ("b", "base"), ("c", "base")]
+            .into_iter()
+            .collect();
+        let mut middle = Properties::with_defaults(base);
+        middle.set_property("b", "middle");
+        let mut props = Properties::with_defaults(middle);
+        props.set_property("d", "top");
+        props.set_property("a", "top");
+
+        assert_eq!(props.get_property("a"), Some("top"));
+        assert_eq!(props.get_property("b"), Some("middle"));
+        assert_eq!(props.get_property("c"), Some("base"));
+        assert_eq!(props.get_property("e"), None);
+        assert_eq!(props.get_property_or("e", "fallback"), "fallback");
+        assert_eq!(
+            props
+                .string_property_names()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["d", "a", "b", "c"]
+        );
+
+        // Removing only uncovers the defaults
+        assert_eq!(props.remove("a").as_deref(), Some("top"));
+        assert_eq!(props.get_property("a"), Some("base"));
+        assert_eq!(props.len(), 1);
+
+        let map = BTreeMap::<String, String>::deserialize(props.into_deserializer()).unwrap();
+        assert_eq!(map.len(), 4);
+        assert_eq!(map["b"], "middle");
+    }
+
+    #[test]
+    fn test_load_store() {
+        let mut props = Properties::new();
+        props.set_property("first", "1");
+        props
+            .load(&b"z=26\nname=J\xfcrgen\nfirst=one\nempty="[..])
+            .unwrap();
+        assert_eq!(
+            props.iter().collect::<Vec<_>>(),
+            [
+                ("first", "one"),
+                ("z", "26"),
+                ("name", "Jürgen"),
+                ("empty", "")
+            ]
+        );
+        assert_eq!(props.get::<u8>("z").unwrap(), Some(26));
+        assert_eq!(props.get::<Option<u8>>("empty").unwrap(), Some(None));
+        assert!(props.get::<u8>("name").is_err());
+
+        let mut out = Vec::new();
+        props
+            .store_with_encoding(&mut out, encoding_rs::UTF_8)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "first=one\nz=26\nname=Jürgen\nempty=\n"
+        );
+    }
+}
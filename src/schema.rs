@@ -0,0 +1,555 @@
+//! Validation of properties against a declarative schema
+//!
+//! A [`Schema`] lists the keys that an input may contain, along with the type of
+//! their values and further constraints. Unlike deserializing into a Rust type, this
+//! can be set up at runtime, e.g. from a schema file that is shipped next to the
+//! configuration, so that people who edit the configuration can check it without
+//! knowing the types behind it.
+//!
+//! A schema file is a properties file itself. Every line sets one attribute of a key,
+//! by appending the name of the attribute to the key:
+//!
+//! ```properties
+//! server.port.type=u16
+//! server.port.required=true
+//! server.port.min=1024
+//! server.host.pattern=[a-z0-9.-]+
+//! log.level.choices=trace, debug, info, warn, error
+//! log.level.description=How much to log
+//! ```
+//!
+//! The attributes are:
+//!
+//! - `type`: the type of the value, checked with the same rules as when
+//!   deserializing. One of `string` (the default), `bool`, `char`, `i8`, `i16`,
+//!   `i32`, `i64`, `u8`, `u16`, `u32`, `u64`, `f32` and `f64`, or the aliases
+//!   `boolean`, `integer` (`i64`) and `number` (`f64`).
+//! - `required`: `true` if the key must be set.
+//! - `min` and `max`: the inclusive range of numbers, or of the length in characters
+//!   for other types.
+//! - `pattern`: a [regular expression](https://docs.rs/regex) that has to match the
+//!   whole value.
+//! - `choices`: a comma separated list of the allowed values.
+//! - `description`: a description for people, which is not checked.
+//!
+//! Keys that are not listed are rejected, unless the schema contains the line
+//! `@allow-unknown=true`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::de::{suggest, Deserializer, Diagnostic, Entry, Error, FieldDeserializer, Part, Report};
+
+/// The schema option that allows keys that are not listed
+const ALLOW_UNKNOWN: &str = "@allow-unknown";
+
+/// The attributes of a key in a schema file
+const ATTRIBUTES: &[&str] = &[
+    "type",
+    "required",
+    "min",
+    "max",
+    "pattern",
+    "choices",
+    "description",
+];
+
+/// The type of a value in a [`Schema`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ValueType {
+    /// Any text
+    #[default]
+    String,
+    /// `true` or `false`
+    Bool,
+    /// A single character
+    Char,
+    /// An `i8`
+    I8,
+    /// An `i16`
+    I16,
+    /// An `i32`
+    I32,
+    /// An `i64`
+    I64,
+    /// A `u8`
+    U8,
+    /// A `u16`
+    U16,
+    /// A `u32`
+    U32,
+    /// A `u64`
+    U64,
+    /// An `f32`
+    F32,
+    /// An `f64`
+    F64,
+}
+
+impl ValueType {
+    /// The name of the type in schema files
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Bool => "bool",
+            Self::Char => "char",
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        }
+    }
+
+    /// Find a type by its name or alias in schema files
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "string" => Self::String,
+            "bool" | "boolean" => Self::Bool,
+            "char" => Self::Char,
+            "i8" => Self::I8,
+            "i16" => Self::I16,
+            "i32" => Self::I32,
+            "i64" | "integer" => Self::I64,
+            "u8" => Self::U8,
+            "u16" => Self::U16,
+            "u32" => Self::U32,
+            "u64" => Self::U64,
+            "f32" => Self::F32,
+            "f64" | "number" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn is_number(self) -> bool {
+        !matches!(self, Self::String | Self::Bool | Self::Char)
+    }
+
+    /// Check that `value` can be deserialized as this type
+    fn check(self, value: &str) -> Result<(), Error> {
+        fn check<'de, T: Deserialize<'de>>(value: &str) -> Result<(), Error> {
+            T::deserialize(FieldDeserializer(value.to_string())).map(drop)
+        }
+        match self {
+            Self::String => Ok(()),
+            Self::Bool => check::<bool>(value),
+            Self::Char => check::<char>(value),
+            Self::I8 => check::<i8>(value),
+            Self::I16 => check::<i16>(value),
+            Self::I32 => check::<i32>(value),
+            Self::I64 => check::<i64>(value),
+            Self::U8 => check::<u8>(value),
+            Self::U16 => check::<u16>(value),
+            Self::U32 => check::<u32>(value),
+            Self::U64 => check::<u64>(value),
+            Self::F32 => check::<f32>(value),
+            Self::F64 => check::<f64>(value),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The constraints on the value of a key
+#[derive(Debug, Clone, Default)]
+pub struct KeySchema {
+    value_type: ValueType,
+    required: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+    /// The pattern as written, and the compiled expression that matches it as a whole
+    pattern: Option<(String, Regex)>,
+    choices: Vec<String>,
+    description: Option<String>,
+}
+
+impl KeySchema {
+    /// Accept values of the given type
+    pub fn new(value_type: ValueType) -> Self {
+        Self {
+            value_type,
+            ..Self::default()
+        }
+    }
+
+    /// Require the key to be set
+    pub fn set_required(&mut self, required: bool) {
+        self.required = required;
+    }
+
+    /// Limit numbers, or the length in characters of other values, to `min..=max`
+    pub fn set_range(&mut self, min: Option<f64>, max: Option<f64>) {
+        self.min = min;
+        self.max = max;
+    }
+
+    /// Require values to match `pattern` as a whole
+    pub fn set_pattern(&mut self, pattern: &str) -> Result<(), regex::Error> {
+        let regex = Regex::new(&format!("^(?:{})$", pattern))?;
+        self.pattern = Some((pattern.to_string(), regex));
+        Ok(())
+    }
+
+    /// Only accept the given values
+    ///
+    /// An empty list accepts every value.
+    pub fn set_choices<I>(&mut self, choices: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.choices = choices.into_iter().map(Into::into).collect();
+    }
+
+    /// Describe the key for people who edit the configuration
+    pub fn set_description(&mut self, description: impl Into<String>) {
+        self.description = Some(description.into());
+    }
+
+    /// The type of the values
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    /// Whether the key must be set
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// The description of the key, if any
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// Check a value, returning the first violated constraint
+    fn check(&self, value: &str) -> Result<(), Error> {
+        self.value_type.check(value)?;
+        let size = match self.value_type.is_number() {
+            true => value.parse::<f64>().unwrap_or(f64::NAN),
+            false => value.chars().count() as f64,
+        };
+        if self.min.is_some_and(|min| size < min) || self.max.is_some_and(|max| size > max) {
+            return Err(Error::OutOfRange {
+                value: value.to_string(),
+                min: self.min,
+                max: self.max,
+                length: !self.value_type.is_number(),
+            });
+        }
+        if let Some((pattern, _)) = self.pattern.as_ref().filter(|(_, p)| !p.is_match(value)) {
+            return Err(Error::PatternMismatch {
+                value: value.to_string(),
+                pattern: pattern.clone(),
+            });
+        }
+        if !self.choices.is_empty() && !self.choices.iter().any(|choice| choice == value) {
+            return Err(Error::InvalidChoice {
+                value: value.to_string(),
+                choices: self.choices.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The keys that an input may contain, and the constraints on their values
+///
+/// See the [module documentation](self) for the format of schema files.
+///
+/// ```
+/// use serde_java_properties::schema::{self, Schema};
+///
+/// let schema: Schema = "
+/// server.port.type=u16
+/// server.port.required=true
+/// log.level.choices=debug, info, warn
+/// ".parse()?;
+///
+/// let report = schema::validate(&schema, "log.level=verbose\nlog.file=app.log").unwrap_err();
+/// assert_eq!(report.to_string(), "\
+/// line 1: `log.level`: invalid value `verbose`, expected one of `debug`, `info`, `warn`
+/// unknown field `log.file` on line 2
+/// `server.port`: missing key `server.port`");
+/// # Ok::<(), serde_java_properties::de::Report>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    keys: IndexMap<String, KeySchema>,
+    allow_unknown: bool,
+}
+
+impl Schema {
+    /// Create a schema without any keys
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a key, replacing an earlier schema for it
+    pub fn add_key(&mut self, key: impl Into<String>, schema: KeySchema) {
+        self.keys.insert(key.into(), schema);
+    }
+
+    /// Accept keys that are not listed, instead of rejecting them
+    pub fn set_allow_unknown(&mut self, allow: bool) {
+        self.allow_unknown = allow;
+    }
+
+    /// The schema of a key
+    pub fn get(&self, key: &str) -> Option<&KeySchema> {
+        self.keys.get(key)
+    }
+
+    /// Check key-value pairs, collecting every violation
+    fn check(&self, entries: &[Entry]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for entry in entries {
+            let result = match self.keys.get(&entry.key) {
+                Some(schema) => schema.check(&entry.value).map_err(|e| (Part::Value, e)),
+                None if self.allow_unknown => Ok(()),
+                None => Err((
+                    Part::Key,
+                    Error::UnknownField {
+                        field: entry.key.clone(),
+                        line: Some(entry.line),
+                        suggestion: suggest(&entry.key, self.keys.keys().map(String::as_str))
+                            .map(str::to_string),
+                    },
+                )),
+            };
+            if let Err((part, error)) = result {
+                diagnostics.push(Diagnostic {
+                    key: Some(entry.key.clone()),
+                    line: Some(entry.line),
+                    part: Some(part),
+                    error,
+                });
+            }
+        }
+        for (key, schema) in &self.keys {
+            if schema.required && !entries.iter().any(|entry| entry.key == *key) {
+                diagnostics.push(Diagnostic {
+                    key: Some(key.clone()),
+                    line: None,
+                    part: None,
+                    error: Error::MissingKey { key: key.clone() },
+                });
+            }
+        }
+        diagnostics
+    }
+}
+
+impl FromStr for Schema {
+    type Err = Report;
+
+    /// Read a schema file, reporting every malformed line
+    fn from_str(text: &str) -> Result<Self, Report> {
+        let entries = read(text)?;
+        let mut schema = Schema::new();
+        let mut diagnostics = Vec::new();
+        for Entry {
+            line, key, value, ..
+        } in entries
+        {
+            let result = match key.rsplit_once('.') {
+                _ if key == ALLOW_UNKNOWN => bool::deserialize(FieldDeserializer(value))
+                    .map(|allow| schema.allow_unknown = allow)
+                    .map_err(|e| (Part::Value, e)),
+                Some((name, attribute)) if ATTRIBUTES.contains(&attribute) => {
+                    let key_schema = schema.keys.entry(name.to_string()).or_default();
+                    set_attribute(key_schema, attribute, value).map_err(|e| (Part::Value, e))
+                }
+                _ => Err((
+                    Part::Key,
+                    Error::UnknownField {
+                        field: key.clone(),
+                        line: Some(line),
+                        suggestion: suggest_attribute(&key),
+                    },
+                )),
+            };
+            if let Err((part, error)) = result {
+                diagnostics.push(Diagnostic {
+                    key: Some(key),
+                    line: Some(line),
+                    part: Some(part),
+                    error,
+                });
+            }
+        }
+        match diagnostics.is_empty() {
+            true => Ok(schema),
+            false => Err(Report { diagnostics }),
+        }
+    }
+}
+
+fn set_attribute(schema: &mut KeySchema, attribute: &str, value: String) -> Result<(), Error> {
+    let number = |value: String| f64::deserialize(FieldDeserializer(value)).map(Some);
+    match attribute {
+        "type" => {
+            schema.value_type =
+                ValueType::from_name(value.trim()).ok_or_else(|| Error::InvalidValue {
+                    expected: "a type such as `string`, `bool`, `u16` or `f64`",
+                    key: None,
+                    line: None,
+                    source: Box::new(Error::Custom {
+                        msg: format!("unknown type `{}`", value),
+                    }),
+                    value,
+                })?;
+        }
+        "required" => schema.required = bool::deserialize(FieldDeserializer(value))?,
+        "min" => schema.min = number(value)?,
+        "max" => schema.max = number(value)?,
+        "pattern" => schema
+            .set_pattern(&value)
+            .map_err(|e| Error::InvalidValue {
+                value,
+                expected: "a regular expression",
+                key: None,
+                line: None,
+                source: Box::new(Error::Custom { msg: e.to_string() }),
+            })?,
+        "choices" => schema.set_choices(value.split(',').map(str::trim)),
+        _ => schema.description = Some(value),
+    }
+    Ok(())
+}
+
+/// Read the key-value pairs of an input, or report why it can't be read
+fn read(input: &str) -> Result<Vec<Entry>, Report> {
+    Deserializer::from_str(input)
+        .into_entries()
+        .map_err(|error| Report {
+            diagnostics: vec![Diagnostic {
+                key: None,
+                line: error.line(),
+                part: None,
+                error,
+            }],
+        })
+}
+
+/// A known attribute, or the schema option, with a similar name to `key`
+fn suggest_attribute(key: &str) -> Option<String> {
+    match key.rsplit_once('.') {
+        Some((name, attribute)) => suggest(attribute, ATTRIBUTES.iter().copied())
+            .map(|attribute| format!("{}.{}", name, attribute)),
+        None => suggest(key, [ALLOW_UNKNOWN]).map(str::to_string),
+    }
+}
+
+/// Check `input` against `schema`, reporting every violation with its line number
+///
+/// Values are typed with the same rules as when deserializing, so a file that
+/// passes can be deserialized into a type with the same fields.
+pub fn validate(schema: &Schema, input: &str) -> Result<(), Report> {
+    let diagnostics = schema.check(&read(input)?);
+    match diagnostics.is_empty() {
+        true => Ok(()),
+        false => Err(Report { diagnostics }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, KeySchema, Schema, ValueType};
+    use crate::de::Error;
+
+    const SCHEMA: &str = "
+server.port.type=u16
+server.port.required=true
+server.port.min=1024
+server.host.pattern=[a-z0-9.-]+
+server.host.description=The name to listen on
+server.name.max=8
+log.level.choices=trace, debug, info
+log.sample.type=number
+log.sample.min=0
+log.sample.max=1
+";
+
+    #[test]
+    fn test_validate() {
+        let schema: Schema = SCHEMA.parse().unwrap();
+        let input = "server.port=8080\nserver.host=localhost\nlog.level=info\nlog.sample=0.5";
+        validate(&schema, input).unwrap();
+
+        let input = "\
+server.port=80
+server.host=Local Host
+server.name=webserver
+log.level=INFO
+log.sample=2
+log.smaple=0.5";
+        let report = validate(&schema, input).unwrap_err();
+        let lines: Vec<_> = report.diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5, 6].map(Some));
+        assert!(matches!(
+            &report.diagnostics[1].error,
+            Error::PatternMismatch { pattern, .. } if pattern == "[a-z0-9.-]+"
+        ));
+        assert_eq!(
+            report.to_string(),
+            "\
+line 1: `server.port`: invalid value `80`, expected at least 1024
+line 2: `server.host`: invalid value `Local Host`, expected a match of `[a-z0-9.-]+`
+line 3: `server.name`: invalid value `webserver`, expected at most 8 characters
+line 4: `log.level`: invalid value `INFO`, expected one of `trace`, `debug`, `info`
+line 5: `log.sample`: invalid value `2`, expected between 0 and 1
+unknown field `log.smaple` on line 6, did you mean `log.sample`?"
+        );
+
+        let report = validate(&schema, "server.port=http").unwrap_err();
+        assert!(matches!(
+            &report.diagnostics[0].error,
+            Error::InvalidValue { expected, .. } if expected.contains("65535")
+        ));
+        let report = validate(&schema, "").unwrap_err();
+        assert!(matches!(
+            &report.diagnostics[0].error,
+            Error::MissingKey { key } if key == "server.port"
+        ));
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema: Schema = SCHEMA.parse().unwrap();
+        let host = schema.get("server.host").unwrap();
+        assert_eq!(host.value_type(), ValueType::String);
+        assert_eq!(host.description(), Some("The name to listen on"));
+        assert!(schema.get("server.port").unwrap().is_required());
+
+        let text = "a.type=u17\na.pattern=(\na.tpye=u8\nb=1\n@allow-unknown=yes";
+        let report = text.parse::<Schema>().unwrap_err();
+        let lines: Vec<_> = report.diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5].map(Some));
+        assert!(matches!(
+            &report.diagnostics[2].error,
+            Error::UnknownField { suggestion: Some(s), .. } if s == "a.type"
+        ));
+
+        let mut schema = Schema::new();
+        let mut port = KeySchema::new(ValueType::U16);
+        port.set_range(Some(1.0), None);
+        schema.add_key("port", port);
+        schema.set_allow_unknown(true);
+        validate(&schema, "port=1\nhost=localhost").unwrap();
+        assert!(validate(&schema, "port=0").is_err());
+    }
+}
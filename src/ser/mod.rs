@@ -3,6 +3,7 @@
 use std::{error, fmt, io};
 
 use encoding_rs::Encoding;
+use indexmap::IndexMap;
 use java_properties::PropertiesError;
 use serde::{
     ser::{self, Impossible},
@@ -11,43 +12,285 @@ use serde::{
 
 use self::string::StringSerializer;
 
+macro_rules! not_a_map {
+    ($($fn_name:ident: $ty:ty),*) => {
+        $(
+            fn $fn_name(self, _v: $ty) -> Result<Self::Ok, Self::Error> {
+                Err(Error::NotAMap)
+            }
+        )*
+    };
+}
+
 mod string;
 
-pub use java_properties::LineEnding;
+pub use crate::de::TransformError;
+pub use java_properties::{LineEnding, PropertiesWriter};
 
-/// Serialize a structure to a properties file
-pub struct Serializer<W: io::Write> {
-    inner: java_properties::PropertiesWriter<W>,
+/// Rewrites values after they are turned into text, before they are written
+///
+/// See [`Serializer::add_transform`]
+pub trait Transform {
+    /// Get the text to write for `key`, whose serialized value is `value`
+    fn transform(&self, key: &str, value: &str) -> Result<String, TransformError>;
 }
 
-impl<W: io::Write> Serializer<W> {
+impl<F> Transform for F
+where
+    F: Fn(&str, &str) -> Result<String, TransformError>,
+{
+    fn transform(&self, key: &str, value: &str) -> Result<String, TransformError> {
+        self(key, value)
+    }
+}
+
+/// Where a [`Serializer`] writes the keys and values of a structure
+///
+/// Values arrive as text, after transforms and encryption. A [`PropertiesWriter`]
+/// escapes and encodes them into a properties file, while a `Vec` or an [`IndexMap`]
+/// collects them as they are.
+pub trait Sink {
+    /// Write `value` under `key`
+    fn write(&mut self, key: &str, value: &str) -> Result<(), Error>;
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn write(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        (**self).write(key, value)
+    }
+}
+
+impl<W: io::Write> Sink for PropertiesWriter<W> {
+    fn write(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        PropertiesWriter::write(self, key, value)?;
+        Ok(())
+    }
+}
+
+impl Sink for Vec<(String, String)> {
+    fn write(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        self.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+impl Sink for IndexMap<String, String> {
+    fn write(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        self.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Collect the key-value pairs of a structure, without encoding or escaping them
+pub(crate) fn to_pairs<T>(value: &T) -> Result<Vec<(String, String)>, Error>
+where
+    T: ?Sized + Serialize,
+{
+    let mut pairs = Vec::new();
+    value.serialize(Serializer::from_sink(&mut pairs))?;
+    Ok(pairs)
+}
+
+/// Serialize a structure to a properties file, or another [`Sink`]
+pub struct Serializer<S> {
+    sink: S,
+    /// Put in front of every key
+    prefix: String,
+    /// The encryptor and the keys whose values it encrypts
+    #[cfg(feature = "jasypt")]
+    encrypt: Option<(crate::jasypt::Jasypt, Vec<String>)>,
+    transforms: Vec<Box<dyn Transform + Send + Sync>>,
+}
+
+impl<W: io::Write> Serializer<PropertiesWriter<W>> {
     /// Set the KV separator
     ///
     /// This method returns an error if the separator is not valid. A separator is
     /// valid if is non-empty and consists only of whitespace characters, except
     /// a single `:` or `=` character.
     pub fn set_kv_separator(&mut self, separator: &str) -> Result<(), Error> {
-        self.inner.set_kv_separator(separator)?;
+        self.sink.set_kv_separator(separator)?;
         Ok(())
     }
 
     /// Set the line ending to `\n`, `\r` or `\r\n`.
     pub fn set_line_ending(&mut self, line_ending: LineEnding) {
-        self.inner.set_line_ending(line_ending);
+        self.sink.set_line_ending(line_ending);
     }
 
     /// Create a serializer from a [`io::Write`] implementation
     pub fn from_writer(writer: W) -> Self {
-        Self {
-            inner: java_properties::PropertiesWriter::new(writer),
-        }
+        Self::from_sink(PropertiesWriter::new(writer))
     }
 
     /// Create a serializer from a [`io::Write`] implementation with a specificed encoding
     pub fn from_writer_with_encoding(writer: W, encoding: &'static Encoding) -> Self {
+        Self::from_sink(PropertiesWriter::new_with_encoding(writer, encoding))
+    }
+}
+
+impl<S: Sink> Serializer<S> {
+    /// Create a serializer that writes keys and values to a [`Sink`]
+    ///
+    /// ```
+    /// # use serde::Serialize;
+    /// #
+    /// #[derive(Serialize)]
+    /// struct Server {
+    ///     host: String,
+    ///     port: u16,
+    /// }
+    ///
+    /// let server = Server { host: "::1".to_string(), port: 8080 };
+    /// let mut pairs = Vec::new();
+    /// server.serialize(serde_java_properties::Serializer::from_sink(&mut pairs)).unwrap();
+    ///
+    /// assert_eq!(pairs[0], ("host".to_string(), "::1".to_string()));
+    /// ```
+    pub fn from_sink(sink: S) -> Self {
         Self {
-            inner: java_properties::PropertiesWriter::new_with_encoding(writer, encoding),
+            sink,
+            prefix: String::new(),
+            #[cfg(feature = "jasypt")]
+            encrypt: None,
+            transforms: Vec::new(),
+        }
+    }
+
+    /// Encrypt the values of the given keys as Jasypt `ENC(...)` values
+    ///
+    /// ```
+    /// # use serde::Serialize;
+    /// use serde_java_properties::jasypt::{Algorithm, Jasypt};
+    ///
+    /// #[derive(Serialize)]
+    /// struct Db {
+    ///     user: String,
+    ///     password: String,
+    /// }
+    ///
+    /// let db = Db { user: "app".to_string(), password: "hunter2".to_string() };
+    /// let jasypt = Jasypt::new(Algorithm::PbeWithHmacSha512AndAes256, "secret");
+    /// let mut buffer = Vec::new();
+    /// let mut ser = serde_java_properties::Serializer::from_writer(&mut buffer);
+    /// ser.encrypt_fields(jasypt, ["password"]);
+    /// db.serialize(ser).unwrap();
+    ///
+    /// let text = String::from_utf8(buffer).unwrap();
+    /// assert!(text.starts_with("user=app\npassword=ENC("));
+    /// ```
+    #[cfg(feature = "jasypt")]
+    pub fn encrypt_fields<I>(&mut self, jasypt: crate::jasypt::Jasypt, keys: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.encrypt = Some((jasypt, keys.into_iter().map(Into::into).collect()));
+    }
+
+    /// Rewrite every value with `transform` before it is written
+    ///
+    /// Transforms run in the order they were added, on the text of each value, and
+    /// before values are encrypted. An error fails serialization with [`Error::Transform`].
+    ///
+    /// ```
+    /// # use serde::Serialize;
+    /// use serde_java_properties::ser::TransformError;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Limits {
+    ///     max: Option<usize>,
+    /// }
+    ///
+    /// let mut buffer = Vec::new();
+    /// let mut ser = serde_java_properties::Serializer::from_writer(&mut buffer);
+    /// ser.add_transform(|_: &str, value: &str| -> Result<String, TransformError> {
+    ///     Ok(if value.is_empty() { "N/A".to_string() } else { value.to_string() })
+    /// });
+    /// Limits { max: None }.serialize(ser).unwrap();
+    ///
+    /// assert_eq!(buffer, b"max=N/A\n");
+    /// ```
+    pub fn add_transform<T>(&mut self, transform: T)
+    where
+        T: Transform + Send + Sync + 'static,
+    {
+        self.transforms.push(Box::new(transform));
+    }
+
+    /// Serialize a structure with `prefix` in front of its keys
+    ///
+    /// The returned serializer writes to this one, so that several structures can be
+    /// written to the same output under different prefixes.
+    ///
+    /// ```
+    /// # use serde::Serialize;
+    /// #
+    /// #[derive(Serialize)]
+    /// struct DataSource {
+    ///     url: String,
+    /// }
+    ///
+    /// #[derive(Serialize)]
+    /// struct Consumer {
+    ///     #[serde(rename = "group-id")]
+    ///     group_id: String,
+    /// }
+    ///
+    /// let mut buffer = Vec::new();
+    /// let mut ser = serde_java_properties::Serializer::from_writer(&mut buffer);
+    /// let ds = DataSource { url: "jdbc:h2:mem".to_string() };
+    /// ds.serialize(ser.with_prefix("spring.datasource.")).unwrap();
+    /// let consumer = Consumer { group_id: "app".to_string() };
+    /// consumer.serialize(ser.with_prefix("kafka.consumer.")).unwrap();
+    ///
+    /// let text = String::from_utf8(buffer).unwrap();
+    /// assert_eq!(text, "spring.datasource.url=jdbc\\:h2\\:mem\nkafka.consumer.group-id=app\n");
+    /// ```
+    pub fn with_prefix(
+        &mut self,
+        prefix: impl Into<String>,
+    ) -> impl ser::Serializer<Ok = (), Error = Error> + '_ {
+        let mut serializer = Serializer::from_sink(self);
+        serializer.prefix = prefix.into();
+        serializer
+    }
+
+    /// Serialize a value and write it under `key`
+    fn write_value<T>(&mut self, key: &str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let value = value.serialize(StringSerializer)?;
+        Sink::write(self, key, &value)
+    }
+}
+
+/// A serializer passes the values it gets on to its own sink, so that
+/// [`Serializer::with_prefix`] can write to it
+impl<S: Sink> Sink for Serializer<S> {
+    fn write(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let key = format!("{}{}", self.prefix, key);
+        let key = key.as_str();
+        let mut value = value.to_string();
+        for transform in &self.transforms {
+            value = transform
+                .transform(key, &value)
+                .map_err(|source| Error::Transform {
+                    key: key.to_string(),
+                    source,
+                })?;
+        }
+        #[cfg(feature = "jasypt")]
+        if let Some((jasypt, keys)) = &self.encrypt {
+            if keys.iter().any(|k| k == key) {
+                value = jasypt.encrypt(&value).map_err(|source| Error::Encryption {
+                    key: key.to_string(),
+                    source,
+                })?;
+            }
         }
+        self.sink.write(key, &value)
     }
 }
 
@@ -64,6 +307,21 @@ pub enum Error {
     },
     /// Not a map
     NotAMap,
+    /// A [`Transform`] rejected a value
+    Transform {
+        /// The key of the value
+        key: String,
+        /// The cause
+        source: TransformError,
+    },
+    /// A value could not be encrypted
+    #[cfg(feature = "jasypt")]
+    Encryption {
+        /// The key of the value
+        key: String,
+        /// The cause
+        source: crate::jasypt::Error,
+    },
     /// Serialization not supported
     NotSupported,
 }
@@ -80,12 +338,28 @@ impl fmt::Display for Error {
             Self::Properties(e) => e.fmt(f),
             Self::Custom { msg } => write!(f, "Serialization error: {}", msg),
             Self::NotAMap => write!(f, "Can only serialize a map-like structure to properties"),
+            Self::Transform { key, source } => {
+                write!(f, "failed to transform `{}`: {}", key, source)
+            }
+            #[cfg(feature = "jasypt")]
+            Self::Encryption { key, source } => {
+                write!(f, "failed to encrypt `{}`: {}", key, source)
+            }
             Self::NotSupported => write!(f, "Not supported"),
         }
     }
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Transform { source, .. } => Some(source.as_ref()),
+            #[cfg(feature = "jasypt")]
+            Self::Encryption { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
 impl ser::Error for Error {
     fn custom<T>(msg: T) -> Self
@@ -98,22 +372,16 @@ impl ser::Error for Error {
     }
 }
 
-impl<W: io::Write> ser::SerializeStruct for Serializer<W> {
+impl<S: Sink> ser::SerializeStruct for Serializer<S> {
     type Ok = ();
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let value = value.serialize(StringSerializer)?;
-        self.inner.write(key, &value)?;
-        Ok(())
+        self.write_value(key, value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
@@ -121,22 +389,16 @@ impl<W: io::Write> ser::SerializeStruct for Serializer<W> {
     }
 }
 
-impl<W: io::Write> ser::SerializeStructVariant for Serializer<W> {
+impl<S: Sink> ser::SerializeStructVariant for Serializer<S> {
     type Ok = ();
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        let value = value.serialize(StringSerializer)?;
-        self.inner.write(key, &value)?;
-        Ok(())
+        self.write_value(key, value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
@@ -145,19 +407,19 @@ impl<W: io::Write> ser::SerializeStructVariant for Serializer<W> {
 }
 
 /// A struct to serialize a map
-pub struct MapSerializer<W: io::Write> {
-    inner: java_properties::PropertiesWriter<W>,
+pub struct MapSerializer<S> {
+    inner: Serializer<S>,
     key: Option<String>,
 }
 
-impl<W: io::Write> ser::SerializeMap for MapSerializer<W> {
+impl<S: Sink> ser::SerializeMap for MapSerializer<S> {
     type Ok = ();
 
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         let str = T::serialize(key, string::StringSerializer)?;
         self.key = Some(str);
@@ -165,14 +427,12 @@ impl<W: io::Write> ser::SerializeMap for MapSerializer<W> {
     }
 
     /// Panics is `serialize_key` wasn't called before successfully
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         let key = self.key.take().unwrap();
-        let value = value.serialize(StringSerializer)?;
-        self.inner.write(&key, &value)?;
-        Ok(())
+        self.inner.write_value(&key, value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
@@ -180,17 +440,7 @@ impl<W: io::Write> ser::SerializeMap for MapSerializer<W> {
     }
 }
 
-macro_rules! not_a_map {
-    ($($fn_name:ident: $ty:ty),*) => {
-        $(
-            fn $fn_name(self, _v: $ty) -> Result<Self::Ok, Self::Error> {
-                Err(Error::NotAMap)
-            }
-        )*
-    };
-}
-
-impl<W: io::Write> ser::Serializer for Serializer<W> {
+impl<S: Sink> ser::Serializer for Serializer<S> {
     type Ok = ();
 
     type Error = Error;
@@ -203,7 +453,7 @@ impl<W: io::Write> ser::Serializer for Serializer<W> {
 
     type SerializeTupleVariant = Impossible<(), Error>;
 
-    type SerializeMap = MapSerializer<W>;
+    type SerializeMap = MapSerializer<S>;
 
     type SerializeStruct = Self;
 
@@ -232,9 +482,9 @@ impl<W: io::Write> ser::Serializer for Serializer<W> {
         Ok(())
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(self)
     }
@@ -256,18 +506,18 @@ impl<W: io::Write> ser::Serializer for Serializer<W> {
         Ok(())
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
         _name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(self)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         _variant_index: u32,
@@ -275,7 +525,7 @@ impl<W: io::Write> ser::Serializer for Serializer<W> {
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(self)
     }
@@ -308,7 +558,7 @@ impl<W: io::Write> ser::Serializer for Serializer<W> {
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
         Ok(MapSerializer {
-            inner: self.inner,
+            inner: self,
             key: None,
         })
     }
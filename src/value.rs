@@ -0,0 +1,572 @@
+//! A dynamically typed representation of properties
+
+use std::fmt;
+
+use indexmap::IndexMap;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, MapAccess, Unexpected, Visitor};
+use serde::ser::{self, SerializeMap};
+use serde::{forward_to_deserialize_any, Deserialize, Serialize};
+
+use crate::de::{Deserializer, Entry, Error, FieldDeserializer};
+
+/// The name of the newtype struct that [`Value`] asks for when deserializing
+///
+/// [`FieldDeserializer`] answers it with the raw text of the value, instead of
+/// guessing a type for it.
+pub(crate) const TOKEN: &str = "$serde_java_properties::Value";
+
+/// Properties without a fixed structure
+///
+/// A value is either the raw text of a property, or a map of keys to values. Values
+/// read from a properties file are flat maps from the full keys to their text. With
+/// [`Value::into_nested`], dotted keys become nested maps, so that `server.port`
+/// is the key `port` in the map at `server`. If a key is both a property and the
+/// prefix of others, its text is stored under the empty key of its map.
+///
+/// ```
+/// use serde_java_properties::Value;
+///
+/// let text = "server.host=localhost\nserver.port=8080\n";
+/// let mut value: Value = serde_java_properties::from_str(text).unwrap();
+///
+/// assert_eq!(value.get::<u16>("server.port").unwrap(), Some(8080));
+/// assert_eq!(value.get::<u16>("server.timeout").unwrap(), None);
+///
+/// value.insert("server.port", "9090");
+/// let value = value.into_nested();
+/// assert_eq!(value.get_value("server").unwrap().as_map().unwrap().len(), 2);
+///
+/// let text = serde_java_properties::to_string(&value).unwrap();
+/// assert_eq!(text, "server.host=localhost\nserver.port=9090\n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// The raw text of a property
+    String(String),
+    /// Keys and their values, in the order they were inserted
+    Map(IndexMap<String, Value>),
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Self::Map(IndexMap::new())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Self::String(text)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Self::String(text.to_string())
+    }
+}
+
+impl From<IndexMap<String, Value>> for Value {
+    fn from(map: IndexMap<String, Value>) -> Self {
+        Self::Map(map)
+    }
+}
+
+impl Value {
+    /// The text, if this is a property
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(text) => Some(text),
+            Self::Map(_) => None,
+        }
+    }
+
+    /// The map, if this is one
+    pub fn as_map(&self) -> Option<&IndexMap<String, Value>> {
+        match self {
+            Self::String(_) => None,
+            Self::Map(map) => Some(map),
+        }
+    }
+
+    /// The map, if this is one
+    pub fn as_map_mut(&mut self) -> Option<&mut IndexMap<String, Value>> {
+        match self {
+            Self::String(_) => None,
+            Self::Map(map) => Some(map),
+        }
+    }
+
+    /// Look up a dotted key, in flat as well as in nested maps
+    pub fn get_value(&self, key: &str) -> Option<&Value> {
+        let map = self.as_map()?;
+        if let Some(value) = map.get(key) {
+            return Some(value);
+        }
+        key.match_indices('.').find_map(|(i, _)| {
+            let child = map.get(&key[..i])?;
+            child.get_value(&key[i + 1..])
+        })
+    }
+
+    /// Look up a dotted key, and deserialize its value as a `T`
+    ///
+    /// Text is typed like values in a properties file, and maps can be
+    /// deserialized as structs.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, Error> {
+        self.get_value(key)
+            .map(|value| T::deserialize(value.clone().into_deserializer()))
+            .transpose()
+    }
+
+    /// Set the property `key` of a map to `value`, returning the previous value
+    ///
+    /// The key is used as is, even if the map is nested. Returns `None` without
+    /// inserting anything if this is not a map.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Value>) -> Option<Value> {
+        self.as_map_mut()?.insert(key.into(), value.into())
+    }
+
+    /// Remove the key from a map, keeping the order of the other keys
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.as_map_mut()?.shift_remove(key)
+    }
+
+    /// Turn nested maps into a single map with dotted keys
+    pub fn into_flat(self) -> Value {
+        match self {
+            Self::String(text) => Self::String(text),
+            Self::Map(map) => {
+                let mut pairs = Vec::new();
+                flatten("", map, &mut pairs);
+                let map = pairs
+                    .into_iter()
+                    .map(|(key, text)| (key, Value::String(text)))
+                    .collect();
+                Self::Map(map)
+            }
+        }
+    }
+
+    /// Turn dotted keys into nested maps
+    ///
+    /// Keys with empty segments, such as `a..b`, are kept as they are.
+    pub fn into_nested(self) -> Value {
+        let map = match self {
+            Self::String(text) => return Self::String(text),
+            Self::Map(map) => map,
+        };
+        let mut pairs = Vec::new();
+        flatten("", map, &mut pairs);
+        let mut nested = IndexMap::new();
+        for (key, text) in pairs {
+            if key.split('.').any(str::is_empty) {
+                nested.insert(key, Value::String(text));
+            } else {
+                insert_nested(&mut nested, &key, text);
+            }
+        }
+        Self::Map(nested)
+    }
+
+    /// The entries of the flattened value, for deserializing it
+    fn into_entries(map: IndexMap<String, Value>) -> Vec<Entry> {
+        let mut pairs = Vec::new();
+        flatten("", map, &mut pairs);
+        pairs
+            .into_iter()
+            .map(|(key, value)| Entry {
+                line: 0,
+                key,
+                value,
+                source: None,
+                raw: None,
+            })
+            .collect()
+    }
+}
+
+fn flatten(prefix: &str, map: IndexMap<String, Value>, pairs: &mut Vec<(String, String)>) {
+    for (key, value) in map {
+        let key = match (prefix, key.as_str()) {
+            ("", _) => key,
+            (_, "") => prefix.to_string(),
+            _ => format!("{}.{}", prefix, key),
+        };
+        match value {
+            Value::String(text) => pairs.push((key, text)),
+            Value::Map(map) => flatten(&key, map, pairs),
+        }
+    }
+}
+
+fn insert_nested(map: &mut IndexMap<String, Value>, key: &str, text: String) {
+    let Some((first, rest)) = key.split_once('.') else {
+        match map.get_mut(key) {
+            Some(Value::Map(child)) => child.insert(String::new(), Value::String(text)),
+            _ => map.insert(key.to_string(), Value::String(text)),
+        };
+        return;
+    };
+    let child = map
+        .entry(first.to_string())
+        .or_insert_with(|| Value::Map(IndexMap::new()));
+    if let Value::String(text) = child {
+        let text = std::mem::take(text);
+        *child = Value::Map(IndexMap::from([(String::new(), Value::String(text))]));
+    }
+    if let Value::Map(child) = child {
+        insert_nested(child, rest, text);
+    }
+}
+
+impl Serialize for Value {
+    /// Text is serialized as a string, and maps as a flat map with dotted keys
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        match self {
+            Self::String(text) => serializer.serialize_str(text),
+            Self::Map(map) => {
+                let mut pairs = Vec::new();
+                flatten("", map.clone(), &mut pairs);
+                let mut map = serializer.serialize_map(Some(pairs.len()))?;
+                for (key, text) in &pairs {
+                    map.serialize_entry(key, text)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a map or a value")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::String(String::new()))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::String(String::new()))
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut map = IndexMap::new();
+        while let Some((key, value)) = access.next_entry::<String, Value>()? {
+            map.insert(key, value);
+        }
+        Ok(Value::Map(map))
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(TOKEN, ValueVisitor)
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = ValueDeserializer;
+
+    fn into_deserializer(self) -> ValueDeserializer {
+        ValueDeserializer(self)
+    }
+}
+
+/// Deserializes a [`Value`]
+///
+/// Text is typed like values in a properties file, and maps are deserialized like
+/// a properties file with their flattened keys.
+pub struct ValueDeserializer(Value);
+
+impl ValueDeserializer {
+    /// The text of the value, which is stored under the empty key for maps
+    fn into_text(self, expected: &dyn de::Expected) -> Result<FieldDeserializer, Error> {
+        match self.0 {
+            Value::String(text) => Ok(FieldDeserializer(text)),
+            Value::Map(mut map) => match map.shift_remove("") {
+                Some(Value::String(text)) => Ok(FieldDeserializer(text)),
+                _ => Err(de::Error::invalid_type(Unexpected::Map, expected)),
+            },
+        }
+    }
+}
+
+macro_rules! deserialize_text {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                self.into_text(&visitor)?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(text) => FieldDeserializer(text).deserialize_any(visitor),
+            Value::Map(map) => {
+                Deserializer::from_entries(Value::into_entries(map)).deserialize_any(visitor)
+            }
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(text) => {
+                FieldDeserializer(text).deserialize_struct(name, fields, visitor)
+            }
+            Value::Map(map) => Deserializer::from_entries(Value::into_entries(map))
+                .deserialize_struct(name, fields, visitor),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(text) => FieldDeserializer(text).deserialize_option(visitor),
+            map => visitor.visit_some(ValueDeserializer(map)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(text) => {
+                FieldDeserializer(text).deserialize_newtype_struct(name, visitor)
+            }
+            map => visitor.visit_newtype_struct(ValueDeserializer(map)),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Value::String(text) => {
+                FieldDeserializer(text).deserialize_enum(name, variants, visitor)
+            }
+            Value::Map(map) => Deserializer::from_entries(Value::into_entries(map))
+                .deserialize_enum(name, variants, visitor),
+        }
+    }
+
+    deserialize_text! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map identifier ignored_any
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl From<Value> for serde_json::Value {
+    /// Text becomes a JSON string, and maps become JSON objects
+    fn from(value: Value) -> Self {
+        match value {
+            Value::String(text) => serde_json::Value::String(text),
+            Value::Map(map) => serde_json::Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| (key, value.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl From<serde_json::Value> for Value {
+    /// Objects become maps, and arrays become maps with the indices as keys. Other
+    /// values become their text, where `null` is empty.
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Value::String(String::new()),
+            serde_json::Value::Bool(v) => Value::String(v.to_string()),
+            serde_json::Value::Number(v) => Value::String(v.to_string()),
+            serde_json::Value::String(text) => Value::String(text),
+            serde_json::Value::Array(items) => Value::Map(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, item)| (i.to_string(), item.into()))
+                    .collect(),
+            ),
+            serde_json::Value::Object(map) => Value::Map(
+                map.into_iter()
+                    .map(|(key, value)| (key, value.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+    use serde::Deserialize;
+
+    use super::Value;
+
+    #[test]
+    fn test_raw_text() {
+        let text = "zip=007\nratio=1.50\nflag=true\nempty=";
+        let value: Value = crate::from_str(text).unwrap();
+        assert_eq!(value.get_value("zip").unwrap().as_str(), Some("007"));
+        assert_eq!(value.get_value("ratio").unwrap().as_str(), Some("1.50"));
+        assert_eq!(value.get::<u32>("zip").unwrap(), Some(7));
+        assert_eq!(value.get::<Option<u32>>("empty").unwrap(), Some(None));
+        assert!(value.get::<bool>("zip").is_err());
+        assert_eq!(crate::to_string(&value).unwrap(), format!("{}\n", text));
+    }
+
+    #[test]
+    fn test_nesting() {
+        let text = "a.b=1\na=0\na.c.d=2\nx..y=3\nb=4";
+        let value: Value = crate::from_str(text).unwrap();
+        let nested = value.clone().into_nested();
+        let a = nested.get_value("a").unwrap().as_map().unwrap();
+        assert_eq!(a.keys().collect::<Vec<_>>(), ["b", "", "c"]);
+        assert_eq!(nested.get::<u8>("a").unwrap(), Some(0));
+        assert_eq!(nested.get::<u8>("a.c.d").unwrap(), Some(2));
+        assert_eq!(nested.get::<u8>("x..y").unwrap(), Some(3));
+        assert!(nested.get::<u8>("a.c").is_err());
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct A {
+            b: u8,
+            #[serde(rename = "c.d")]
+            d: u8,
+        }
+        let a = nested.get::<A>("a").unwrap().unwrap();
+        assert_eq!(a, A { b: 1, d: 2 });
+
+        assert_eq!(nested.clone().into_flat(), value);
+        assert_eq!(nested.into_nested().into_flat(), value);
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut value = Value::default();
+        value.insert("server.port", "80");
+        value.insert("server.host", "localhost");
+        value.insert("server.port", "8080");
+        assert_eq!(value.remove("server.host"), Some(Value::from("localhost")));
+        assert_eq!(value.get::<u16>("server.port").unwrap(), Some(8080));
+        assert_eq!(Value::from("text").insert("a", "b"), None);
+
+        let map = IndexMap::from([("port".to_string(), Value::from("8080"))]);
+        value.insert("client", map);
+        assert_eq!(value.get::<u16>("client.port").unwrap(), Some(8080));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_json() {
+        let json = serde_json::json!({
+            "server": { "port": 8080, "tls": true, "hosts": ["a", "b"] },
+            "name": null,
+        });
+        let value = Value::from(json);
+        assert_eq!(value.get::<u16>("server.port").unwrap(), Some(8080));
+        assert_eq!(value.get::<String>("server.hosts.1").unwrap().unwrap(), "b");
+        let text = crate::to_string(&value).unwrap();
+        assert_eq!(
+            text,
+            "name=\nserver.hosts.0=a\nserver.hosts.1=b\nserver.port=8080\nserver.tls=true\n"
+        );
+
+        let value: Value = crate::from_str(&text).unwrap();
+        let json = serde_json::Value::from(value.into_nested());
+        assert_eq!(json["server"]["hosts"]["0"], "a");
+
+        let value: Value = serde_json::from_str(r#"{"a": {"b": 1.5}}"#).unwrap();
+        assert_eq!(value.get::<f64>("a.b").unwrap(), Some(1.5));
+    }
+}